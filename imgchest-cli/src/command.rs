@@ -1 +1,2 @@
-pub mod download;
+pub mod download;
+pub mod info;
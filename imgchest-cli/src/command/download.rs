@@ -1,9 +1,6 @@
-use anyhow::ensure;
 use anyhow::Context;
 use std::path::Path;
 use std::path::PathBuf;
-use tokio::task::JoinSet;
-use url::Url;
 
 #[derive(Debug, argh::FromArgs)]
 #[argh(
@@ -23,6 +20,20 @@ pub struct Options {
         description = "the directory to download to"
     )]
     pub out_dir: PathBuf,
+
+    #[argh(
+        switch,
+        long = "incremental",
+        description = "only download files that are new or changed since the last download"
+    )]
+    pub incremental: bool,
+
+    #[argh(
+        switch,
+        long = "html",
+        description = "write an index.html to view the post offline"
+    )]
+    pub html: bool,
 }
 
 pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
@@ -33,100 +44,358 @@ pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<
         .await
         .context("failed to get post")?;
 
+    download_post(&client, &post, &options).await
+}
+
+/// Download a post that was already fetched.
+async fn download_post(
+    client: &imgchest::Client,
+    post: &imgchest::ScrapedPost,
+    options: &Options,
+) -> anyhow::Result<()> {
     let out_dir = options.out_dir.join(&*post.id);
 
     tokio::fs::create_dir_all(&out_dir)
         .await
         .context("failed to create out dir")?;
 
-    let post_json = serde_json::to_string(&post)?;
-    tokio::fs::write(out_dir.join("post.json"), &post_json).await?;
+    let post_json_path = out_dir.join("post.json");
+    let post_json = serde_json::to_string(&post.to_versioned_json())?;
 
-    let mut join_set = JoinSet::new();
-    let total_downloads = post.image_count;
-    for image in post.images.iter() {
-        spawn_image_download(&client, &mut join_set, image, &out_dir);
+    // This is written even if nothing changed,
+    // so an archive made without it can gain one.
+    if options.html {
+        let index_html = render_index_html(post)?;
+        tokio::fs::write(out_dir.join("index.html"), index_html)
+            .await
+            .context("failed to write index.html")?;
     }
 
-    let mut last_error = Ok(());
-    let mut downloaded = 0;
-    while let Some(result) = join_set.join_next().await {
-        match result
-            .context("failed to join tokio task")
-            .and_then(|result| result)
-        {
-            Ok(_new_download) => {
-                downloaded += 1;
-                println!("{downloaded}/{total_downloads}...");
-            }
-            Err(error) => {
-                eprintln!("{error:?}");
-                last_error = Err(error);
+    // The ids of the files to download, or `None` for every file.
+    let selected: Option<Vec<&str>> = if options.incremental {
+        let old_post = read_old_post(&post_json_path).await?;
+        let changes = PostChanges::new(old_post.as_ref(), post, &out_dir);
+
+        let unchanged = match old_post.as_ref() {
+            Some(old_post) => {
+                changes.is_empty()
+                    && serde_json::to_string(&old_post.to_versioned_json())? == post_json
             }
+            None => false,
+        };
+        if unchanged {
+            println!("no changes since the last download");
+            return Ok(());
         }
+
+        println!(
+            "{} new, {} changed, {} missing, {} removed",
+            changes.added.len(),
+            changes.changed.len(),
+            changes.missing.len(),
+            changes.removed
+        );
+
+        Some(
+            changes
+                .added
+                .into_iter()
+                .chain(changes.changed)
+                .chain(changes.missing)
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    tokio::fs::write(&post_json_path, &post_json).await?;
+
+    if post.images.is_empty() {
+        println!("post has no images");
+        return Ok(());
+    }
+
+    let summary = client
+        .download_post_filtered(post, &out_dir, |image| {
+            selected
+                .as_ref()
+                .is_none_or(|selected| selected.contains(&&*image.id))
+        })
+        .await
+        .context("failed to download post")?;
+    println!(
+        "{} downloaded, {} already present",
+        summary.downloaded, summary.skipped
+    );
+    if summary.unsupported > 0 {
+        println!(
+            "{} files are not on the cdn and were not downloaded",
+            summary.unsupported
+        );
+    }
+
+    let mut last_error = Ok(());
+    for (file, error) in summary.failed {
+        let error =
+            anyhow::Error::from(error).context(format!("failed to download \"{}\"", file.link));
+        eprintln!("{error:?}");
+        last_error = Err(error);
     }
 
     last_error
 }
 
-fn extract_id(value: &str) -> anyhow::Result<String> {
-    match Url::parse(value) {
-        Ok(url) => {
-            // Ensure the url is in the format:
-            // https://imgchest.com/p/{id}
-            ensure!(url.host_str() == Some("imgchest.com"));
-            let mut path_iter = url.path_segments().context("url is missing path")?;
-            ensure!(path_iter.next() == Some("p"));
-            let id = path_iter.next().context("url missing id path segment")?;
-
-            Ok(id.to_string())
+/// Read the post metadata from a previous download, if it exists.
+async fn read_old_post(path: &Path) -> anyhow::Result<Option<imgchest::ScrapedPost>> {
+    let post_json = match tokio::fs::read_to_string(path).await {
+        Ok(post_json) => post_json,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error).context("failed to read old post.json"),
+    };
+    let post = serde_json::from_str(&post_json)
+        .and_then(imgchest::ScrapedPost::migrate_from_json)
+        .context("failed to parse old post.json")?;
+
+    Ok(Some(post))
+}
+
+/// The file changes between two versions of a post.
+#[derive(Debug)]
+struct PostChanges<'a> {
+    /// The ids of files that are new.
+    added: Vec<&'a str>,
+
+    /// The ids of files whose link changed.
+    changed: Vec<&'a str>,
+
+    /// The ids of unchanged files that are not in the out dir,
+    /// like ones that failed to download last time.
+    missing: Vec<&'a str>,
+
+    /// The number of files that were removed.
+    removed: usize,
+}
+
+impl<'a> PostChanges<'a> {
+    fn new(
+        old: Option<&imgchest::ScrapedPost>,
+        new: &'a imgchest::ScrapedPost,
+        out_dir: &Path,
+    ) -> Self {
+        let old_images = old.map(|old| &*old.images).unwrap_or_default();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut missing = Vec::new();
+        for image in new.images.iter() {
+            match old_images.iter().find(|old_image| old_image.id == image.id) {
+                Some(old_image) if old_image.link != image.link => changed.push(&*image.id),
+                Some(_) => {
+                    let is_downloadable = image.kind() != imgchest::FileKind::Other;
+                    let path = imgchest::Client::download_path(out_dir, image);
+                    if is_downloadable && !path.exists() {
+                        missing.push(&*image.id);
+                    }
+                }
+                None => added.push(&*image.id),
+            }
         }
-        Err(_error) => {
-            // This isn't a url, but it might be a raw id.
-            let is_valid_id = is_valid_id(value);
-            ensure!(
-                is_valid_id,
-                "ids must be composed of 11 ascii alphanumeric characters"
-            );
-            Ok(value.to_string())
+
+        let removed = old_images
+            .iter()
+            .filter(|old_image| !new.images.iter().any(|image| image.id == old_image.id))
+            .count();
+
+        Self {
+            added,
+            changed,
+            missing,
+            removed,
         }
     }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.missing.is_empty()
+            && self.removed == 0
+    }
 }
 
-/// Ids are composed of 11 lowercase alphanumeric chars.
-fn is_valid_id(value: &str) -> bool {
-    value.len() == 11 && value.chars().all(is_ascii_alphanumeric_lowercase)
+pub(crate) fn extract_id(value: &str) -> anyhow::Result<String> {
+    let post_ref: imgchest::PostRef = value.parse()?;
+    Ok(post_ref.post_id()?.to_string())
 }
 
-fn is_ascii_alphanumeric_lowercase(ch: char) -> bool {
-    ch.is_ascii_digit() | ch.is_ascii_lowercase()
+/// Render a page that displays a downloaded post.
+fn render_index_html(post: &imgchest::ScrapedPost) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut images: Vec<_> = post.images.iter().collect();
+    images.sort_by_key(|image| image.position);
+
+    let title = escape_html(&post.title);
+    let username = escape_html(&post.username);
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>{title}</title>")?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<h1>{title}</h1>")?;
+    writeln!(html, "<p>by {username}</p>")?;
+    if let Some(description) = post.description.as_deref() {
+        writeln!(html, "<p>{}</p>", escape_html(description))?;
+    }
+    for image in images {
+        let kind = image.kind();
+
+        writeln!(html, "<figure>")?;
+        if kind == imgchest::FileKind::Other {
+            // These are not downloaded, so link to them instead.
+            let link = escape_html(&image.link);
+            writeln!(html, "<a href=\"{link}\">{link}</a>")?;
+        } else {
+            let file_name = image.file_name().context("missing file name")?;
+            let file_name = escape_html(file_name);
+            if kind == imgchest::FileKind::Video {
+                writeln!(html, "<video src=\"{file_name}\" controls></video>")?;
+            } else {
+                writeln!(html, "<img src=\"{file_name}\">")?;
+            }
+        }
+        if let Some(description) = image.description.as_deref() {
+            writeln!(
+                html,
+                "<figcaption>{}</figcaption>",
+                escape_html(description)
+            )?;
+        }
+        writeln!(html, "</figure>")?;
+    }
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+
+    Ok(html)
 }
 
-fn spawn_image_download(
-    client: &imgchest::Client,
-    join_set: &mut JoinSet<anyhow::Result<bool>>,
-    file: &imgchest::ScrapedPostFile,
-    out_dir: &Path,
-) {
-    let client = client.clone();
-    let link = file.link.clone();
-    let out_path_result = file
-        .link
-        .split('/')
-        .next_back()
-        .context("missing file name")
-        .map(|file_name| out_dir.join(file_name));
-    join_set.spawn(async move {
-        let out_path = out_path_result?;
-        if tokio::fs::try_exists(&out_path)
-            .await
-            .context("failed to check if file exists")?
-        {
-            return Ok(false);
+/// Escape text for use in html content and quoted attributes.
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            ch => escaped.push(ch),
         }
+    }
+    escaped
+}
 
-        nd_util::download_to_path(&client.client, &link, &out_path).await?;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        Ok(true)
-    });
+    /// Get an empty temporary directory that no other test or test run uses.
+    fn test_dir(name: &str) -> PathBuf {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "imgchest-cli-test-{}-{name}-{n}",
+            std::process::id()
+        ));
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => panic!("failed to remove old test dir: {error}"),
+        }
+        dir
+    }
+
+    fn post(file_ids: &[&str]) -> imgchest::ScrapedPost {
+        let images: Vec<_> = file_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                serde_json::json!({
+                    "id": id,
+                    "description": null,
+                    "link": format!("https://cdn.imgchest.com/files/{id}.png"),
+                    "position": i + 1,
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": "3qe4gdvj4j2",
+            "title": "title",
+            "username": "LunarLandr",
+            "views": 0,
+            "nsfw": false,
+            "image_count": images.len(),
+            "images": images,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn post_changes_missing_files() {
+        let out_dir = test_dir("post_changes_missing_files");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::write(out_dir.join("present.png"), b"").unwrap();
+
+        // The old post.json lists both files, but one failed to download.
+        let old = post(&["present", "failed"]);
+        let new = post(&["present", "failed"]);
+        let changes = PostChanges::new(Some(&old), &new, &out_dir);
+        assert!(!changes.is_empty());
+        assert!(changes.added.is_empty());
+        assert!(changes.changed.is_empty());
+        assert!(changes.missing == ["failed"]);
+
+        std::fs::write(out_dir.join("failed.png"), b"").unwrap();
+        let changes = PostChanges::new(Some(&old), &new, &out_dir);
+        assert!(changes.is_empty());
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn incremental_html_without_changes() {
+        let options = Options {
+            url: "3qe4gdvj4j2".into(),
+            out_dir: test_dir("incremental_html_without_changes"),
+            incremental: true,
+            html: true,
+        };
+        let post = post(&["present"]);
+
+        // An up-to-date archive that was made without --html.
+        let post_dir = options.out_dir.join(&*post.id);
+        std::fs::create_dir_all(&post_dir).unwrap();
+        std::fs::write(post_dir.join("present.png"), b"").unwrap();
+        std::fs::write(
+            post_dir.join("post.json"),
+            serde_json::to_string(&post.to_versioned_json()).unwrap(),
+        )
+        .unwrap();
+
+        download_post(&imgchest::Client::new(), &post, &options)
+            .await
+            .expect("failed to download post");
+        let index_html = std::fs::read_to_string(post_dir.join("index.html"))
+            .expect("index.html should be written");
+        assert!(
+            index_html.contains("<img src=\"present.png\">"),
+            "{index_html}"
+        );
+
+        std::fs::remove_dir_all(&options.out_dir).unwrap();
+    }
 }
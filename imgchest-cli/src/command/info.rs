@@ -0,0 +1,134 @@
+use super::download::extract_id;
+use anyhow::bail;
+use anyhow::Context;
+use time::format_description::well_known::Rfc3339;
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "info",
+    description = "print details about a post or user from imgchest.com"
+)]
+pub struct Options {
+    #[argh(positional, description = "the url or id of the post")]
+    pub url: Option<String>,
+
+    #[argh(
+        option,
+        long = "user",
+        description = "a user to print instead of a post"
+    )]
+    pub user: Option<String>,
+
+    #[argh(
+        option,
+        long = "token",
+        description = "the api token, defaults to the IMGCHEST_TOKEN env var"
+    )]
+    pub token: Option<String>,
+
+    #[argh(switch, long = "json", description = "print the details as json")]
+    pub json: bool,
+}
+
+pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
+    let token = options
+        .token
+        .or_else(|| std::env::var("IMGCHEST_TOKEN").ok());
+    let has_token = token.is_some();
+    if let Some(token) = token {
+        client.set_token(token);
+    }
+
+    match (options.url, options.user) {
+        (Some(url), None) => {
+            let id = extract_id(url.as_str()).context("failed to determine post id")?;
+            if has_token {
+                let post = client.get_post(&id).await.context("failed to get post")?;
+                if options.json {
+                    println!("{}", serde_json::to_string_pretty(&post)?);
+                } else {
+                    print_post(&post)?;
+                }
+            } else {
+                let post = client
+                    .get_scraped_post(&id)
+                    .await
+                    .context("failed to get post")?;
+                if options.json {
+                    println!("{}", serde_json::to_string_pretty(&post)?);
+                } else {
+                    print_scraped_post(&post);
+                }
+            }
+        }
+        (None, Some(username)) => {
+            if has_token {
+                let user = client
+                    .get_user(&username)
+                    .await
+                    .context("failed to get user")?;
+                if options.json {
+                    println!("{}", serde_json::to_string_pretty(&user)?);
+                } else {
+                    println!("Name: {}", user.name);
+                    println!("Posts: {}", user.posts);
+                    println!("Comments: {}", user.comments);
+                    println!("Created: {}", user.created.format(&Rfc3339)?);
+                }
+            } else {
+                let post_ids = client
+                    .scrape_user_post_ids(&username)
+                    .await
+                    .context("failed to get user posts")?;
+                if options.json {
+                    let value = serde_json::json!({
+                        "name": username,
+                        "post_ids": post_ids,
+                    });
+                    println!("{}", serde_json::to_string_pretty(&value)?);
+                } else {
+                    println!("Name: {username}");
+                    println!("Posts: {}", post_ids.len());
+                    for id in post_ids {
+                        println!("  https://imgchest.com/p/{id}");
+                    }
+                }
+            }
+        }
+        _ => bail!("specify either a post url or a user"),
+    }
+
+    Ok(())
+}
+
+fn print_post(post: &imgchest::Post) -> anyhow::Result<()> {
+    println!("Title: {}", post.title.as_deref().unwrap_or(""));
+    println!("Author: {}", post.username);
+    println!("Privacy: {}", post.privacy.as_str());
+    println!("Views: {}", post.views);
+    println!("Images: {}", post.image_count);
+    println!("NSFW: {}", post.nsfw);
+    println!("Created: {}", post.created.format(&Rfc3339)?);
+    for image in post.images.iter() {
+        match image.original_name.as_deref() {
+            Some(original_name) => {
+                println!("  {}. {} ({original_name})", image.position, image.link)
+            }
+            None => println!("  {}. {}", image.position, image.link),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_scraped_post(post: &imgchest::ScrapedPost) {
+    println!("Title: {}", post.title);
+    println!("Author: {}", post.username);
+    println!("Views: {}", post.views);
+    println!("Images: {}", post.image_count);
+    println!("NSFW: {}", post.nsfw);
+    for image in post.images.iter() {
+        println!("  {}. {}", image.position, image.link);
+    }
+}
@@ -11,6 +11,7 @@ struct Options {
 #[argh(subcommand)]
 enum Subcommand {
     Download(self::command::download::Options),
+    Info(self::command::info::Options),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -26,6 +27,7 @@ async fn async_main(options: Options) -> anyhow::Result<()> {
 
     match options.subcommand {
         Subcommand::Download(options) => self::command::download::exec(client, options).await?,
+        Subcommand::Info(options) => self::command::info::exec(client, options).await?,
     }
 
     Ok(())
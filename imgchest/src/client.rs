@@ -1,30 +1,108 @@
+mod archive;
+mod clock;
+mod download;
+mod interceptor;
+mod manifest;
+mod metrics;
+#[cfg(feature = "zip")]
+mod zip;
+
+pub use self::archive::ArchiveProgress;
+pub use self::archive::ArchiveSummary;
+pub use self::archive::ArchivedFile;
+pub use self::archive::ArchivedPost;
+pub use self::clock::Clock;
+pub use self::clock::TokioClock;
+pub use self::download::DownloadSummary;
+pub use self::download::DownloadedFile;
+pub use self::download::PostSource;
+use self::download::Throttle;
+pub use self::interceptor::RequestInterceptor;
+pub use self::manifest::ManifestFile;
+pub use self::manifest::PostManifest;
+pub use self::metrics::ClientMetrics;
+use self::metrics::Endpoint;
+use self::metrics::Metrics;
+use crate::parse_post_views;
 use crate::ApiCompletedResponse;
+use crate::ApiPostCover;
 use crate::ApiResponse;
 use crate::ApiUpdateFilesBulkRequest;
 use crate::Error;
 use crate::FileUpdate;
+use crate::InvalidScrapedPostError;
 use crate::Post;
 use crate::PostFile;
+use crate::PostMeta;
 use crate::PostPrivacy;
+use crate::PostRef;
 use crate::ScrapedPost;
+use crate::ScrapedUser;
+use crate::ScrapedUserPostsPage;
 use crate::User;
 use reqwest::header::AUTHORIZATION;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::ETAG;
+use reqwest::header::IF_MODIFIED_SINCE;
+use reqwest::header::IF_NONE_MATCH;
+use reqwest::header::LAST_MODIFIED;
+use reqwest::header::LOCATION;
 use reqwest::multipart::Form;
+use reqwest::StatusCode;
 use scraper::Html;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::num::NonZeroU32;
+use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 use tokio_util::codec::BytesCodec;
 use tokio_util::codec::FramedRead;
 
-const REQUESTS_PER_MINUTE: u8 = 60;
+const REQUESTS_PER_MINUTE: u32 = 60;
 const ONE_MINUTE: Duration = Duration::from_secs(60);
 const API_BASE: &str = "https://api.imgchest.com";
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_SCRAPE_BODY_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+const MAX_REDIRECTS: usize = 10;
+const SCRAPE_HOST: &str = "imgchest.com";
+const CF_MITIGATED: &str = "cf-mitigated";
+
+/// The multipart field name for uploaded images.
+pub(crate) const IMAGES_FIELD: &str = "images[]";
+
+/// The multipart field name for uploaded image descriptions.
+pub(crate) const DESCRIPTIONS_FIELD: &str = "descriptions[]";
+
+/// The maximum number of images to send in a single upload request.
+///
+/// The API does not document a limit, so this is a conservative guess.
+/// Raise it with [`ClientBuilder::max_images_per_request`] if the server accepts more.
+pub const MAX_IMAGES_PER_REQUEST: usize = 20;
+
+/// The file extensions imgchest is known to accept for uploads, in lowercase.
+///
+/// This is best-effort; the server is authoritative.
+/// This is the default list used by [`Client::validate_upload`].
+pub const SUPPORTED_UPLOAD_EXTENSIONS: &[&str] =
+    &["gif", "jpeg", "jpg", "mov", "mp4", "png", "webm", "webp"];
 
 /// A builder for creating a post.
 ///
 /// This builder is for the low-level function.
+///
+/// The API does not allow choosing a post's id or slug.
+/// It is always assigned by the server.
+///
+/// The API also has no way to make a post expire or to schedule its deletion.
+/// Posts remain until they are deleted with [`Client::delete_post`].
 #[derive(Debug)]
 pub struct CreatePostBuilder {
     /// The title of the post.
@@ -37,7 +115,11 @@ pub struct CreatePostBuilder {
     /// Defaults to hidden.
     pub privacy: Option<PostPrivacy>,
 
-    /// Whether the post should be tied to the user.
+    /// Whether the post should not be tied to the user.
+    ///
+    /// The API still requires a token for anonymous posts;
+    /// this only detaches the post from the token's account.
+    /// There is no known way to upload without a token.
     pub anonymous: Option<bool>,
 
     /// Whether this post is nsfw.
@@ -45,6 +127,17 @@ pub struct CreatePostBuilder {
 
     /// The images of the post
     pub images: Vec<UploadPostFile>,
+
+    /// Descriptions to give images, keyed by file name.
+    ///
+    /// These are matched to images when the post is sent,
+    /// replacing any description set on the image itself.
+    pub descriptions_by_filename: HashMap<String, String>,
+
+    /// Whether descriptions for file names that match no image are an error.
+    ///
+    /// Defaults to false, which ignores them.
+    pub deny_unmatched_descriptions: bool,
 }
 
 impl CreatePostBuilder {
@@ -56,6 +149,8 @@ impl CreatePostBuilder {
             anonymous: None,
             nsfw: None,
             images: Vec::new(),
+            descriptions_by_filename: HashMap::new(),
+            deny_unmatched_descriptions: false,
         }
     }
 
@@ -76,6 +171,8 @@ impl CreatePostBuilder {
     }
 
     /// Set whether this post should be anonymous.
+    ///
+    /// This does not remove the need for a token.
     pub fn anonymous(&mut self, anonymous: bool) -> &mut Self {
         self.anonymous = Some(anonymous);
         self
@@ -92,6 +189,41 @@ impl CreatePostBuilder {
         self.images.push(file);
         self
     }
+
+    /// Set descriptions to give images, keyed by file name.
+    ///
+    /// These are matched to images when the post is sent,
+    /// so images may be added in any order.
+    pub fn descriptions_by_filename(&mut self, descriptions: HashMap<String, String>) -> &mut Self {
+        self.descriptions_by_filename = descriptions;
+        self
+    }
+
+    /// Set whether descriptions for file names that match no image are an error.
+    ///
+    /// Defaults to false, which ignores them.
+    pub fn deny_unmatched_descriptions(&mut self, deny_unmatched_descriptions: bool) -> &mut Self {
+        self.deny_unmatched_descriptions = deny_unmatched_descriptions;
+        self
+    }
+
+    /// Move the descriptions keyed by file name onto their images.
+    pub(crate) fn apply_descriptions_by_filename(&mut self) -> Result<(), Error> {
+        let mut descriptions = std::mem::take(&mut self.descriptions_by_filename);
+        for file in self.images.iter_mut() {
+            if let Some(description) = descriptions.remove(&file.file_name) {
+                file.description = Some(description);
+            }
+        }
+
+        if self.deny_unmatched_descriptions {
+            if let Some(file_name) = descriptions.into_keys().min() {
+                return Err(Error::UnmatchedDescription { file_name });
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for CreatePostBuilder {
@@ -108,6 +240,12 @@ pub struct UploadPostFile {
 
     /// The file body
     body: reqwest::Body,
+
+    /// The image width and height, if known
+    dimensions: Option<(u32, u32)>,
+
+    /// The file description
+    description: Option<String>,
 }
 
 impl UploadPostFile {
@@ -116,6 +254,8 @@ impl UploadPostFile {
         Self {
             file_name: file_name.into(),
             body,
+            dimensions: None,
+            description: None,
         }
     }
 
@@ -124,6 +264,20 @@ impl UploadPostFile {
         Self::from_body(file_name, file_data.into())
     }
 
+    /// Create this from static bytes, like those from `include_bytes!`.
+    ///
+    /// This does not copy the data.
+    pub fn from_static(file_name: &str, file_data: &'static [u8]) -> Self {
+        Self::from_body(file_name, file_data.into())
+    }
+
+    /// Create this from shared bytes.
+    ///
+    /// This does not copy the data.
+    pub fn from_bytes_shared(file_name: &str, file_data: bytes::Bytes) -> Self {
+        Self::from_body(file_name, file_data.into())
+    }
+
     /// Create this from a file.
     pub fn from_file(file_name: &str, file: tokio::fs::File) -> Self {
         let stream = FramedRead::new(file, BytesCodec::new());
@@ -141,16 +295,124 @@ impl UploadPostFile {
 
         let file_name = path
             .file_name()
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "missing file name"))?
+            .ok_or_else(|| std::io::Error::other("missing file name"))?
             .to_str()
-            .ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::Other, "file name is not valid unicode")
-            })?;
+            .ok_or_else(|| std::io::Error::other("file name is not valid unicode"))?;
 
         let file = tokio::fs::File::open(path).await?;
 
         Ok(Self::from_file(file_name, file))
     }
+
+    /// Create this from a file at the given path, reading the image dimensions first.
+    ///
+    /// Files that are not valid images are rejected.
+    /// Formats that cannot be decoded, like videos, are accepted without dimensions.
+    #[cfg(feature = "image")]
+    pub async fn from_path_with_dimensions<P>(path: P) -> std::io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+
+        let dimensions = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || image::image_dimensions(path)
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+        let dimensions = match dimensions {
+            Ok(dimensions) => Some(dimensions),
+            Err(image::ImageError::Unsupported(_)) => None,
+            Err(image::ImageError::IoError(error)) => return Err(error),
+            Err(error) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, error)),
+        };
+
+        let mut file = Self::from_path(path).await?;
+        file.dimensions = dimensions;
+
+        Ok(file)
+    }
+
+    /// Set the file name, replacing the one this was created with.
+    ///
+    /// The extension of the name is used to validate the upload.
+    pub fn with_name(mut self, file_name: &str) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    /// Set the description of this file.
+    ///
+    /// This is only used when creating a post.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Get the file name.
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Get the file description, if set.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Get the image width and height, if known.
+    ///
+    /// This is only populated by [`UploadPostFile::from_path_with_dimensions`].
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.dimensions
+    }
+}
+
+/// The priority of a rate-limited request.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum RequestPriority {
+    /// A normal request.
+    ///
+    /// If [`ClientBuilder::high_priority_reserve`] is set,
+    /// these leave that part of each window's budget unused, reserved for high priority requests.
+    #[default]
+    Normal,
+
+    /// A high priority request, like one a user is waiting on.
+    ///
+    /// These may use the reserved part of the budget,
+    /// so they do not wait behind normal requests that are waiting for the next window.
+    High,
+}
+
+/// A post along with the validators needed to check whether it changed.
+#[derive(Debug)]
+pub struct CachedPost {
+    /// The post
+    pub post: Post,
+
+    /// The `ETag` of the response
+    pub etag: Option<Box<str>>,
+
+    /// The `Last-Modified` time of the response
+    pub last_modified: Option<Box<str>>,
+}
+
+/// A post from the API, merged with what could be scraped from its page.
+///
+/// The API is authoritative, so only fields the API does not have are taken from the page.
+#[derive(Debug)]
+pub struct FullPost {
+    /// The post from the API
+    pub post: Post,
+
+    /// The id of the post's author, scraped from the page
+    ///
+    /// This is `None` if scraping failed or the page did not include it.
+    pub user_id: Option<u64>,
+
+    /// The error from scraping the page, if it failed
+    pub scrape_error: Option<Error>,
 }
 
 /// A builder for updating a post.
@@ -197,6 +459,107 @@ impl UpdatePostBuilder {
         self.nsfw = Some(nsfw);
         self
     }
+
+    /// Get the fields of this update that do not match the given post.
+    ///
+    /// Given the post returned by [`Client::update_post`],
+    /// this is the list of fields the server did not apply.
+    /// A title the server only trimmed counts as applied.
+    /// A title that may have been truncated does not,
+    /// see [`UpdatePostBuilder::title_outcome`].
+    pub fn unapplied_fields(&self, post: &Post) -> Vec<UpdatePostField> {
+        let mut fields = Vec::new();
+
+        if let Some(TitleOutcome::Truncated { .. } | TitleOutcome::NotApplied { .. }) =
+            self.title_outcome(post)
+        {
+            fields.push(UpdatePostField::Title);
+        }
+
+        if let Some(privacy) = self.privacy {
+            if post.privacy != privacy {
+                fields.push(UpdatePostField::Privacy);
+            }
+        }
+
+        if let Some(nsfw) = self.nsfw {
+            if post.nsfw != nsfw {
+                fields.push(UpdatePostField::Nsfw);
+            }
+        }
+
+        fields
+    }
+
+    /// Get how the server applied the title of this update.
+    ///
+    /// Given the post returned by [`Client::update_post`],
+    /// this reports whether the server changed the title that was sent, and what it stored instead.
+    /// Returns `None` if this update does not change the title.
+    pub fn title_outcome(&self, post: &Post) -> Option<TitleOutcome> {
+        let sent = self.title.as_deref()?;
+        let effective = post.title.as_deref().unwrap_or_default();
+
+        let outcome = if effective == sent {
+            TitleOutcome::Applied
+        } else if effective == sent.trim() {
+            TitleOutcome::Trimmed {
+                effective: effective.into(),
+            }
+        } else if !effective.is_empty() && sent.trim_start().starts_with(effective) {
+            TitleOutcome::Truncated {
+                effective: effective.into(),
+            }
+        } else {
+            TitleOutcome::NotApplied {
+                effective: post.title.clone(),
+            }
+        };
+
+        Some(outcome)
+    }
+}
+
+/// How the server applied the title of a post update.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TitleOutcome {
+    /// The title was stored as it was sent
+    Applied,
+
+    /// The server trimmed whitespace from the title
+    Trimmed {
+        /// The title the server stored
+        effective: Box<str>,
+    },
+
+    /// The stored title is a prefix of the title that was sent
+    ///
+    /// The server may have shortened the title,
+    /// but this is also what a rename that extends the old title looks like if it was not applied,
+    /// so [`UpdatePostBuilder::unapplied_fields`] does not count this as applied.
+    Truncated {
+        /// The title the server stored
+        effective: Box<str>,
+    },
+
+    /// The server did not apply the title
+    NotApplied {
+        /// The title the post still has
+        effective: Option<Box<str>>,
+    },
+}
+
+/// A field of a post update.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum UpdatePostField {
+    /// The title
+    Title,
+
+    /// The privacy
+    Privacy,
+
+    /// The nsfw flag
+    Nsfw,
 }
 
 impl Default for UpdatePostBuilder {
@@ -205,105 +568,1385 @@ impl Default for UpdatePostBuilder {
     }
 }
 
-/// The client
-#[derive(Debug, Clone)]
-pub struct Client {
-    /// The inner http client
-    pub client: reqwest::Client,
+/// A builder for a client.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    /// The maximum size of a scraped page, in bytes.
+    ///
+    /// Defaults to 10 MiB.
+    pub max_scrape_body_size: u64,
 
-    /// Inner client state
-    state: Arc<ClientState>,
+    /// The maximum total download speed, in bytes per second.
+    ///
+    /// Defaults to unlimited.
+    pub max_download_bytes_per_second: Option<u64>,
+
+    /// The file extensions that are accepted for uploads, in lowercase.
+    ///
+    /// Defaults to [`SUPPORTED_UPLOAD_EXTENSIONS`].
+    pub upload_extensions: Vec<String>,
+
+    /// The maximum number of idle connections kept open per host.
+    ///
+    /// Defaults to 32.
+    pub pool_max_idle_per_host: usize,
+
+    /// Whether to use HTTP/2 without negotiating it first.
+    ///
+    /// Defaults to false.
+    pub http2_prior_knowledge: bool,
+
+    /// The interval of TCP keepalive probes.
+    ///
+    /// Defaults to 60 seconds.
+    pub tcp_keepalive: Option<Duration>,
+
+    /// Whether scraped pages follow redirects.
+    ///
+    /// Defaults to true.
+    pub follow_scrape_redirects: bool,
+
+    /// The maximum number of files a post may have,
+    /// checked before adding images to a post.
+    ///
+    /// Defaults to `None`, which disables the check.
+    pub max_files_per_post: Option<u64>,
+
+    /// The maximum length of a file description, in chars.
+    ///
+    /// Defaults to `None`, which disables the check.
+    pub max_description_length: Option<usize>,
+
+    /// The timeout of scraping requests.
+    ///
+    /// Defaults to `None`, which gives scraping requests the same timeout as other requests.
+    pub scrape_timeout: Option<Duration>,
+
+    /// Whether creating a post requires its privacy to be set.
+    ///
+    /// Defaults to false.
+    pub require_explicit_privacy: bool,
+
+    /// Whether upload file names are sanitized before uploading.
+    ///
+    /// Defaults to false.
+    pub sanitize_file_names: bool,
+
+    /// How long a post that was not found is remembered as missing.
+    ///
+    /// Defaults to `None`, which disables remembering missing posts.
+    pub missing_post_ttl: Option<Duration>,
+
+    /// The maximum number of images to send in a single upload request.
+    ///
+    /// Defaults to [`MAX_IMAGES_PER_REQUEST`].
+    pub max_images_per_request: usize,
+
+    /// The clock used by the rate limiter.
+    ///
+    /// Defaults to [`TokioClock`].
+    pub clock: Arc<dyn Clock>,
+
+    /// The base url to download cdn files from instead of the cdn.
+    ///
+    /// Defaults to `None`, which leaves links untouched.
+    pub cdn_base: Option<String>,
+
+    /// The interceptors that run on every request, in order.
+    ///
+    /// Defaults to none.
+    pub interceptors: Vec<Arc<dyn RequestInterceptor>>,
+
+    /// Whether the download helpers download nsfw posts.
+    ///
+    /// Defaults to true.
+    pub allow_nsfw: bool,
+
+    /// The number of API requests each token may make per ratelimit window.
+    ///
+    /// Defaults to 60, the server's current limit.
+    /// 0 disables the ratelimit.
+    pub requests_per_minute: u32,
+
+    /// The length of a ratelimit window.
+    ///
+    /// Defaults to one minute.
+    /// A zero duration disables the ratelimit.
+    pub rate_limit_window: Duration,
+
+    /// The number of requests per ratelimit window that only high priority requests may use.
+    ///
+    /// Defaults to 0, which reserves nothing.
+    pub high_priority_reserve: u32,
 }
 
-impl Client {
-    /// Make a new client
+impl ClientBuilder {
+    /// Create a new builder with the default settings.
     pub fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .cookie_store(true)
-            .build()
-            .expect("failed to build client");
-        let state = Arc::new(ClientState::new());
-
-        Self { client, state }
+        Self {
+            max_scrape_body_size: DEFAULT_MAX_SCRAPE_BODY_SIZE,
+            max_download_bytes_per_second: None,
+            upload_extensions: SUPPORTED_UPLOAD_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            http2_prior_knowledge: false,
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            follow_scrape_redirects: true,
+            max_files_per_post: None,
+            max_description_length: None,
+            scrape_timeout: None,
+            require_explicit_privacy: false,
+            sanitize_file_names: false,
+            missing_post_ttl: None,
+            max_images_per_request: MAX_IMAGES_PER_REQUEST,
+            clock: Arc::new(TokioClock),
+            cdn_base: None,
+            interceptors: Vec::new(),
+            allow_nsfw: true,
+            requests_per_minute: REQUESTS_PER_MINUTE,
+            rate_limit_window: ONE_MINUTE,
+            high_priority_reserve: 0,
+        }
     }
 
-    /// Scrape a post from a post id.
+    /// Set the maximum size of a scraped page, in bytes.
     ///
-    /// # Authorization
-    /// This function does NOT require the use of a token.
-    pub async fn get_scraped_post(&self, id: &str) -> Result<ScrapedPost, Error> {
-        let url = format!("https://imgchest.com/p/{id}");
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
-
-        let post = tokio::task::spawn_blocking(move || {
-            let html = Html::parse_document(text.as_str());
-            ScrapedPost::from_html(&html)
-        })
-        .await??;
+    /// Larger pages are rejected before they are parsed.
+    pub fn max_scrape_body_size(&mut self, max_scrape_body_size: u64) -> &mut Self {
+        self.max_scrape_body_size = max_scrape_body_size;
+        self
+    }
 
-        Ok(post)
+    /// Set the maximum total download speed, in bytes per second.
+    ///
+    /// This limit is shared by all concurrent downloads of the client.
+    /// A limit of 0 is treated as unlimited.
+    pub fn max_download_bytes_per_second(
+        &mut self,
+        max_download_bytes_per_second: Option<u64>,
+    ) -> &mut Self {
+        self.max_download_bytes_per_second = max_download_bytes_per_second;
+        self
     }
 
-    /// Set the token to use for future requests.
+    /// Set the file extensions that are accepted for uploads.
     ///
-    /// This allows the use of functions that require authorization.
-    pub fn set_token<T>(&self, token: T)
+    /// Files with other extensions are rejected before uploading.
+    pub fn upload_extensions<I, S>(&mut self, upload_extensions: I) -> &mut Self
     where
-        T: AsRef<str>,
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
     {
-        *self
-            .state
-            .token
-            .write()
-            .unwrap_or_else(|error| error.into_inner()) = Some(token.as_ref().into());
+        self.upload_extensions = upload_extensions
+            .into_iter()
+            .map(|extension| extension.into().to_ascii_lowercase())
+            .collect();
+        self
     }
 
-    /// Get the current token.
-    fn get_token(&self) -> Option<Arc<str>> {
-        self.state
-            .token
-            .read()
-            .unwrap_or_else(|error| error.into_inner())
-            .clone()
+    /// Set the maximum number of idle connections kept open per host.
+    ///
+    /// Higher values let many concurrent downloads reuse connections,
+    /// at the cost of holding more sockets open.
+    pub fn pool_max_idle_per_host(&mut self, pool_max_idle_per_host: usize) -> &mut Self {
+        self.pool_max_idle_per_host = pool_max_idle_per_host;
+        self
     }
 
-    /// Get a post by id.
+    /// Set whether to use HTTP/2 without negotiating it first.
     ///
-    /// # Authorization
-    /// This function REQUIRES a token.
-    pub async fn get_post(&self, id: &str) -> Result<Post, Error> {
-        let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}");
+    /// HTTP/2 is already used over TLS when the server offers it.
+    /// Enabling this skips the negotiation,
+    /// but requests will fail against any server that does not support HTTP/2.
+    pub fn http2_prior_knowledge(&mut self, http2_prior_knowledge: bool) -> &mut Self {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
 
-        self.state.ratelimit().await;
+    /// Set the interval of TCP keepalive probes.
+    ///
+    /// Keepalives help idle pooled connections survive between requests.
+    /// `None` disables them.
+    pub fn tcp_keepalive(&mut self, tcp_keepalive: Option<Duration>) -> &mut Self {
+        self.tcp_keepalive = tcp_keepalive;
+        self
+    }
 
-        let response = self
-            .client
-            .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+    /// Set whether scraped pages follow redirects.
+    ///
+    /// If disabled, a scraped page that redirects,
+    /// like a removed post redirecting to another page,
+    /// fails with [`Error::PageRedirected`] instead.
+    /// Api and download requests always follow redirects.
+    pub fn follow_scrape_redirects(&mut self, follow_scrape_redirects: bool) -> &mut Self {
+        self.follow_scrape_redirects = follow_scrape_redirects;
+        self
+    }
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+    /// Set the maximum number of files a post may have.
+    ///
+    /// If set, [`Client::add_post_images`] fetches the post first
+    /// and fails with [`Error::PostFileLimitExceeded`] before uploading anything
+    /// if the new images would exceed the limit.
+    /// This costs an extra [`Client::get_post`] request per call.
+    pub fn max_files_per_post(&mut self, max_files_per_post: Option<u64>) -> &mut Self {
+        self.max_files_per_post = max_files_per_post;
+        self
+    }
 
-        Ok(post.data)
+    /// Set the maximum length of a file description, in chars.
+    ///
+    /// If set, longer descriptions fail with [`Error::DescriptionTooLong`]
+    /// before any request is made.
+    /// The server's limit is not documented, so this is not checked by default.
+    pub fn max_description_length(&mut self, max_description_length: Option<usize>) -> &mut Self {
+        self.max_description_length = max_description_length;
+        self
     }
 
-    /// Create a post.
+    /// Set the timeout of scraping requests.
     ///
-    /// # Authorization
-    /// This function REQUIRES a token.
-    pub async fn create_post(&self, data: CreatePostBuilder) -> Result<Post, Error> {
-        let token = self.get_token().ok_or(Error::MissingToken)?;
+    /// This applies to the whole request, including reading the page,
+    /// and only to scraping methods like [`Client::get_scraped_post`].
+    /// API requests and downloads are unaffected.
+    pub fn scrape_timeout(&mut self, scrape_timeout: Duration) -> &mut Self {
+        self.scrape_timeout = Some(scrape_timeout);
+        self
+    }
+
+    /// Set whether creating a post requires its privacy to be set.
+    ///
+    /// If enabled, [`Client::create_post`] fails with [`Error::PrivacyNotSpecified`]
+    /// instead of letting the post default to hidden.
+    pub fn require_explicit_privacy(&mut self, require_explicit_privacy: bool) -> &mut Self {
+        self.require_explicit_privacy = require_explicit_privacy;
+        self
+    }
+
+    /// Set whether the download helpers download nsfw posts.
+    ///
+    /// If disabled, downloading the files of an nsfw post fails with [`Error::NsfwPostRefused`]
+    /// before any file is downloaded.
+    /// This applies to [`Client::download_post_filtered`], [`Client::fetch_and_download`],
+    /// [`Client::archive_posts`], and `Client::download_post_to_zip`.
+    ///
+    /// This relies on the post being flagged as nsfw upstream,
+    /// so posts that are not flagged correctly are still downloaded.
+    pub fn allow_nsfw(&mut self, allow_nsfw: bool) -> &mut Self {
+        self.allow_nsfw = allow_nsfw;
+        self
+    }
+
+    /// Set the number of API requests each token may make per ratelimit window.
+    ///
+    /// Defaults to 60, the server's current limit.
+    /// Raise it for accounts with a higher limit, or lower it to be gentler on the API.
+    ///
+    /// Setting this to 0 disables the ratelimit, so requests never wait.
+    /// The server may still reject requests over its own limit.
+    pub fn requests_per_minute(&mut self, requests_per_minute: u32) -> &mut Self {
+        self.requests_per_minute = requests_per_minute;
+        self
+    }
+
+    /// Set the length of a ratelimit window.
+    ///
+    /// Each token's budget of [`ClientBuilder::requests_per_minute`] is refilled once per window.
+    /// Defaults to one minute.
+    ///
+    /// Setting this to zero disables the ratelimit, so requests never wait.
+    pub fn rate_limit_window(&mut self, rate_limit_window: Duration) -> &mut Self {
+        self.rate_limit_window = rate_limit_window;
+        self
+    }
+
+    /// Set the number of requests per ratelimit window that only high priority requests may use.
+    ///
+    /// Defaults to 0, which reserves nothing, so every request shares the full budget.
+    ///
+    /// A reserve lets [`RequestPriority::High`] requests, like ones a user is waiting on,
+    /// skip ahead of background work that has spent the rest of the budget.
+    /// The trade-off is that normal requests, including every request without a priority variant
+    /// like creating, updating, and deleting, only get the budget minus the reserve,
+    /// and wait for the next window even while reserved requests go unused.
+    /// It is clamped to one less than [`ClientBuilder::requests_per_minute`],
+    /// so normal requests can always make progress.
+    pub fn high_priority_reserve(&mut self, high_priority_reserve: u32) -> &mut Self {
+        self.high_priority_reserve = high_priority_reserve;
+        self
+    }
+
+    /// Set whether upload file names are sanitized before uploading.
+    ///
+    /// Sanitizing a file name:
+    /// * strips any directory components, split on `/` or `\`,
+    /// * replaces every char that is not alphanumeric, `-`, `_`, or `.` with `_`,
+    /// * strips leading dots, so the file is not hidden,
+    /// * and uses `file` as the name if nothing is left.
+    ///
+    /// The extension is sanitized the same way, but is otherwise preserved.
+    pub fn sanitize_file_names(&mut self, sanitize_file_names: bool) -> &mut Self {
+        self.sanitize_file_names = sanitize_file_names;
+        self
+    }
+
+    /// Set how long a post that was not found is remembered as missing.
+    ///
+    /// While a post is remembered as missing,
+    /// getting it from the API fails with [`Error::PostKnownMissing`] without making a request.
+    /// A deleted post could be restored, or a post could be missing due to a server error,
+    /// so a remembered post may become stale; keep this short.
+    ///
+    /// Found posts are never cached.
+    /// `None` disables remembering missing posts.
+    pub fn missing_post_ttl(&mut self, missing_post_ttl: Option<Duration>) -> &mut Self {
+        self.missing_post_ttl = missing_post_ttl;
+        self
+    }
+
+    /// Set the maximum number of images to send in a single upload request.
+    ///
+    /// Uploads with more images fail with [`Error::TooManyImages`] before anything is sent.
+    /// This is also the default batch size of [`Client::create_large_post`].
+    /// Defaults to [`MAX_IMAGES_PER_REQUEST`], a conservative guess at the server's limit.
+    pub fn max_images_per_request(&mut self, max_images_per_request: usize) -> &mut Self {
+        self.max_images_per_request = max_images_per_request;
+        self
+    }
+
+    /// Set the clock used by the rate limiter.
+    ///
+    /// This is meant for tests, which can use a clock that does not really sleep.
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the base url to download cdn files from instead of the cdn.
+    ///
+    /// Links to the cdn have their scheme, host, and port replaced with those of this url
+    /// before downloading, preserving the path and query.
+    /// Other links are untouched.
+    /// This is useful for mirrors, caching proxies, and tests.
+    pub fn cdn_base(&mut self, cdn_base: Option<String>) -> &mut Self {
+        self.cdn_base = cdn_base;
+        self
+    }
+
+    /// Add an interceptor that runs on every request.
+    ///
+    /// Interceptors run in the order they are added.
+    pub fn interceptor(&mut self, interceptor: Arc<dyn RequestInterceptor>) -> &mut Self {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// Build the client.
+    pub fn build(&self) -> Result<Client, Error> {
+        let follow_scrape_redirects = self.follow_scrape_redirects;
+        let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+            let is_scrape = attempt
+                .previous()
+                .first()
+                .is_some_and(|url| url.host_str() == Some(SCRAPE_HOST));
+            if is_scrape && !follow_scrape_redirects {
+                attempt.stop()
+            } else if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let mut client = reqwest::Client::builder()
+            .cookie_store(true)
+            .redirect(redirect_policy)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive);
+        if self.http2_prior_knowledge {
+            client = client.http2_prior_knowledge();
+        }
+        let client = client.build()?;
+
+        let cdn_base = self
+            .cdn_base
+            .as_deref()
+            .map(|cdn_base| {
+                reqwest::Url::parse(cdn_base)
+                    .ok()
+                    .filter(|url| url.has_host())
+                    .ok_or(Error::InvalidCdnBase)
+            })
+            .transpose()?;
+        let state = Arc::new(ClientState::new(self, cdn_base));
+
+        Ok(Client {
+            client,
+            state,
+            token_override: None,
+            timeout_override: None,
+        })
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The client
+///
+/// This must be used from within a tokio runtime with the time driver enabled.
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// The inner http client
+    pub client: reqwest::Client,
+
+    /// Inner client state
+    state: Arc<ClientState>,
+
+    /// The token to use instead of the stored tokens, if any
+    token_override: Option<Arc<TokenState>>,
+
+    /// The timeout to apply to every request, if any
+    timeout_override: Option<Duration>,
+}
+
+impl Client {
+    /// Make a new client
+    pub fn new() -> Self {
+        Self::builder().build().expect("failed to build client")
+    }
+
+    /// Make a builder for a client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Shut down this client handle.
+    ///
+    /// The client does not currently spawn any background tasks,
+    /// as all work is driven by the futures of the calls that started it.
+    /// This is a no-op that only drops this handle.
+    /// It is reserved for stopping background tasks, if any are added in the future.
+    pub async fn shutdown(self) {}
+
+    /// Get a snapshot of the request counters of this client.
+    ///
+    /// Counters are shared by all clones of this client.
+    pub fn metrics(&self) -> ClientMetrics {
+        self.state.metrics.snapshot()
+    }
+
+    /// Reset the request counters of this client to 0.
+    pub fn reset_metrics(&self) {
+        self.state.metrics.reset();
+    }
+
+    /// Send a request, recording it in the metrics.
+    async fn send(
+        &self,
+        endpoint: Endpoint,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let request = match self.timeout_override {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let result = if self.state.interceptors.is_empty() {
+            request.send().await
+        } else {
+            let (client, request) = request.build_split();
+            match request {
+                Ok(mut request) => {
+                    for interceptor in self.state.interceptors.iter() {
+                        interceptor.intercept(&mut request);
+                    }
+                    client.execute(request).await
+                }
+                Err(error) => Err(error),
+            }
+        };
+        self.state.metrics.record(endpoint, &result);
+        result
+    }
+
+    /// Scrape a post from a post id.
+    ///
+    /// Large posts may not embed all of their files in the page.
+    /// If a token is set, the missing files are fetched with [`Client::get_post`].
+    /// Otherwise, a warning is logged and the post's images will be incomplete.
+    ///
+    /// Concurrent calls for the same id share a single request,
+    /// and a successful result is cloned to all of them.
+    /// If the shared request fails, each waiting call makes its own request instead.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn get_scraped_post(&self, id: &str) -> Result<ScrapedPost, Error> {
+        let sender = {
+            let mut in_flight = self
+                .state
+                .in_flight_scrapes
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            match in_flight.get(id) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = tokio::sync::watch::channel(None);
+                    in_flight.insert(id.into(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+
+        let sender = match sender {
+            Ok(sender) => sender,
+            Err(mut receiver) => {
+                if let Ok(result) = receiver.wait_for(Option::is_some).await {
+                    if let Some(Some(post)) = &*result {
+                        return Ok(post.clone());
+                    }
+                }
+
+                return self.get_scraped_post_uncoalesced(id).await;
+            }
+        };
+
+        // Remove the entry even if this future is dropped before completing.
+        let _guard = InFlightScrapeGuard {
+            state: &self.state,
+            id,
+        };
+        let result = self.get_scraped_post_uncoalesced(id).await;
+        sender.send_replace(Some(result.as_ref().ok().cloned()));
+
+        result
+    }
+
+    /// Scrape a post from a post url or id.
+    ///
+    /// See [`PostRef`] for the accepted forms.
+    /// File urls are rejected, as a file's post cannot be found from its url.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn get_scraped_post_from_url(&self, url: &str) -> Result<ScrapedPost, Error> {
+        let post_ref: PostRef = url.parse()?;
+        self.get_scraped_post(post_ref.post_id()?).await
+    }
+
+    /// Scrape a post from a post id, without sharing the request.
+    async fn get_scraped_post_uncoalesced(&self, id: &str) -> Result<ScrapedPost, Error> {
+        let text = self.fetch_post_html(id).await?;
+
+        let mut post =
+            tokio::task::spawn_blocking(move || ScrapedPost::from_html_str(&text)).await??;
+
+        if post.is_incomplete() {
+            if self.get_token().is_some() {
+                let api_post = self.get_post(id).await?;
+                post.images = api_post
+                    .images
+                    .into_vec()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect();
+            } else {
+                tracing::warn!(
+                    id,
+                    image_count = post.image_count,
+                    num_images = post.images.len(),
+                    "scraped post is missing files and no token is set to fetch them"
+                );
+            }
+        }
+
+        Ok(post)
+    }
+
+    /// Fetch the raw html of a post page.
+    ///
+    /// This is the page that [`Client::get_scraped_post`] parses,
+    /// and can be saved to re-parse later.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn fetch_post_html(&self, id: &str) -> Result<String, Error> {
+        let url = format!("https://imgchest.com/p/{id}");
+        self.fetch_html(&url).await
+    }
+
+    /// Scrape only the view count of a post.
+    ///
+    /// This is cheaper than [`Client::get_scraped_post`] for polling,
+    /// as the post's files are not parsed.
+    /// It is not coalesced with other requests for the same post.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn get_post_views(&self, id: &str) -> Result<u64, Error> {
+        let text = self.fetch_post_html(id).await?;
+
+        let views = tokio::task::spawn_blocking(move || {
+            let html = Html::parse_document(text.as_str());
+            parse_post_views(&html)
+        })
+        .await??;
+
+        Ok(views)
+    }
+
+    /// Fetch a page for scraping.
+    ///
+    /// Pages are always decoded as UTF-8, which is what imgchest serves.
+    /// A different charset in the `Content-Type` is ignored with a warning.
+    async fn fetch_html(&self, url: &str) -> Result<String, Error> {
+        let mut request = self.client.get(url);
+        if let Some(scrape_timeout) = self.state.scrape_timeout {
+            request = request.timeout(scrape_timeout);
+        }
+        let response = self.send(Endpoint::Scrape, request).await?;
+
+        // Challenge pages are usually served with an error status.
+        if response.headers().contains_key(CF_MITIGATED) {
+            return Err(Error::InvalidScrapedPost(
+                InvalidScrapedPostError::Challenge,
+            ));
+        }
+        let response = response.error_for_status()?;
+
+        // Redirects are only returned if following them is disabled.
+        if response.status().is_redirection() {
+            let to = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            return Err(Error::PageRedirected {
+                to,
+                status: response.status(),
+            });
+        }
+
+        // Redirects to login or maintenance pages might not be html.
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        let is_html = content_type.is_some_and(|content_type| {
+            content_type
+                .split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/html"))
+        });
+        if !is_html {
+            return Err(Error::UnexpectedContentType {
+                content_type: content_type.map(Into::into),
+                status: response.status(),
+            });
+        }
+        if let Some(charset) = content_type.and_then(content_type_charset) {
+            if !charset.eq_ignore_ascii_case("utf-8") && !charset.eq_ignore_ascii_case("utf8") {
+                tracing::warn!(
+                    charset,
+                    "scraped page is not UTF-8, decoding it as UTF-8 anyway"
+                );
+            }
+        }
+
+        self.read_scrape_body(response).await
+    }
+
+    /// Read the body of a scraped page, enforcing the size limit.
+    async fn read_scrape_body(&self, mut response: reqwest::Response) -> Result<String, Error> {
+        let limit = self.state.max_scrape_body_size;
+        if response
+            .content_length()
+            .is_some_and(|content_length| content_length > limit)
+        {
+            return Err(Error::ResponseTooLarge { limit });
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if u64::try_from(body.len() + chunk.len()).unwrap() > limit {
+                return Err(Error::ResponseTooLarge { limit });
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(decode_scrape_body(body))
+    }
+
+    /// Scrape the ids of all of a user's posts.
+    ///
+    /// This visits every page of the user's profile.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn scrape_user_post_ids(&self, username: &str) -> Result<Vec<String>, Error> {
+        let url = format!("https://imgchest.com/u/{username}?page=1");
+        let first_page = self.fetch_user_posts_page(&url).await?;
+
+        self.scrape_remaining_user_post_ids(username, first_page)
+            .await
+    }
+
+    /// Collect the post ids of the first page of a user's profile and every page after it.
+    ///
+    /// Only the pages after the first are fetched.
+    async fn scrape_remaining_user_post_ids(
+        &self,
+        username: &str,
+        mut posts_page: ScrapedUserPostsPage,
+    ) -> Result<Vec<String>, Error> {
+        let mut ids = Vec::new();
+        let mut seen = HashSet::new();
+        let mut page = 1;
+        loop {
+            let is_empty = posts_page.ids.is_empty();
+            for id in posts_page.ids {
+                if seen.insert(id.clone()) {
+                    ids.push(id.into());
+                }
+            }
+
+            if is_empty || page >= posts_page.last_page {
+                break;
+            }
+            page += 1;
+
+            let url = format!("https://imgchest.com/u/{username}?page={page}");
+            posts_page = self.fetch_user_posts_page(&url).await?;
+        }
+
+        Ok(ids)
+    }
+
+    /// Fetch and parse a page of a user's profile.
+    async fn fetch_user_posts_page(&self, url: &str) -> Result<ScrapedUserPostsPage, Error> {
+        let text = self.fetch_html(url).await.map_err(into_user_error)?;
+
+        tokio::task::spawn_blocking(move || {
+            let html = Html::parse_document(text.as_str());
+            ScrapedUserPostsPage::from_html(&html)
+        })
+        .await?
+        .map_err(Error::InvalidScrapedUser)
+    }
+
+    /// Scrape a user from their profile.
+    ///
+    /// This is a tokenless alternative to [`Client::get_user`].
+    /// Only the name, post count, and the ids of the posts on the first page can be scraped.
+    /// This is usually a single request.
+    /// If the profile does not report a post count, the posts are counted instead
+    /// by visiting every page of the profile after the first,
+    /// which costs a scrape request per page.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn get_scraped_user(&self, username: &str) -> Result<ScrapedUser, Error> {
+        let url = format!("https://imgchest.com/u/{username}");
+        let mut posts_page = self.fetch_user_posts_page(&url).await?;
+
+        let recent_post_ids = posts_page.ids.clone();
+        let avatar = posts_page.avatar.take();
+        let posts = match posts_page.total {
+            Some(total) => total,
+            None => {
+                let ids = self
+                    .scrape_remaining_user_post_ids(username, posts_page)
+                    .await?;
+                u64::try_from(ids.len()).unwrap()
+            }
+        };
+
+        Ok(ScrapedUser {
+            name: username.into(),
+            posts,
+            recent_post_ids,
+            avatar,
+        })
+    }
+
+    /// Check that the API is reachable.
+    ///
+    /// This makes a single HEAD request to the API base with a short timeout.
+    /// Any response that is not a server error is considered healthy.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn health_check(&self) -> Result<(), Error> {
+        let request = self.client.head(API_BASE).timeout(HEALTH_CHECK_TIMEOUT);
+        let response = self.send(Endpoint::Other, request).await?;
+
+        if response.status().is_server_error() {
+            response.error_for_status()?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the token to use for future requests.
+    ///
+    /// This allows the use of functions that require authorization.
+    /// This replaces any tokens added with [`Client::add_token`].
+    pub fn set_token<T>(&self, token: T)
+    where
+        T: AsRef<str>,
+    {
+        *self
+            .state
+            .tokens
+            .write()
+            .unwrap_or_else(|error| error.into_inner()) =
+            vec![Arc::new(self.new_token_state(token))];
+    }
+
+    /// Make the state of a new token, with this client's clock and ratelimit.
+    fn new_token_state<T>(&self, token: T) -> TokenState
+    where
+        T: AsRef<str>,
+    {
+        TokenState::new(token, self.state.clock.clone(), self.state.rate_limit)
+    }
+
+    /// Add a token to the pool of tokens used for future requests.
+    ///
+    /// Each token has its own ratelimit,
+    /// and each request uses the next token that has requests remaining.
+    /// Make sure that using multiple accounts this way is allowed by imgchest's terms of service.
+    pub fn add_token<T>(&self, token: T)
+    where
+        T: AsRef<str>,
+    {
+        self.state
+            .tokens
+            .write()
+            .unwrap_or_else(|error| error.into_inner())
+            .push(Arc::new(self.new_token_state(token)));
+    }
+
+    /// Make a handle to this client that authorizes requests with the given token.
+    ///
+    /// The stored tokens are ignored and left unchanged,
+    /// so this is safe for serving many users from one client.
+    /// The handle shares the connection pool and all other state with this client.
+    ///
+    /// The ratelimit of the token is shared by every handle made for the same token,
+    /// including the stored token's if it is one.
+    pub fn with_token<T>(&self, token: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        let token = token.as_ref();
+        let stored = self
+            .state
+            .tokens
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .iter()
+            .find(|stored| &*stored.value == token)
+            .cloned();
+        let token_state = stored.unwrap_or_else(|| {
+            let mut override_tokens = self
+                .state
+                .override_tokens
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+
+            // A token that no handle uses anymore and whose window is over has a full budget,
+            // so dropping it loses nothing.
+            override_tokens
+                .retain(|_, state| Arc::strong_count(state) > 1 || !state.is_window_over());
+
+            override_tokens
+                .entry(token.into())
+                .or_insert_with(|| Arc::new(self.new_token_state(token)))
+                .clone()
+        });
+
+        Self {
+            token_override: Some(token_state),
+            ..self.clone()
+        }
+    }
+
+    /// Make a handle to this client that applies the given timeout to each of its requests.
+    ///
+    /// The timeout covers the whole request, including reading the response body.
+    /// It overrides any other timeout for requests made with the returned handle,
+    /// including [`ClientBuilder::scrape_timeout`].
+    /// The handle shares the connection pool and all other state with this client.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout_override: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Refill the ratelimit of every token of this client and start a new window.
+    ///
+    /// This is meant for tests, and for apps that know a new window started on the server.
+    /// Misuse makes the client send more requests than the server allows,
+    /// which fail with 429 Too Many Requests.
+    ///
+    /// This includes the tokens given to [`Client::with_token`].
+    pub fn reset_rate_limit(&self) {
+        for token in self
+            .state
+            .override_tokens
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .values()
+        {
+            token.reset_ratelimit();
+        }
+
+        for token in self
+            .state
+            .tokens
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .iter()
+        {
+            token.reset_ratelimit();
+        }
+    }
+
+    /// Get the token to use for the next request.
+    ///
+    /// This is the token given to [`Client::with_token`], if any.
+    /// Otherwise, this prefers tokens which have requests remaining, in round-robin order.
+    pub(crate) fn get_token(&self) -> Option<Arc<TokenState>> {
+        if let Some(token) = self.token_override.as_ref() {
+            return Some(token.clone());
+        }
+
+        let tokens = self
+            .state
+            .tokens
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        if tokens.is_empty() {
+            return None;
+        }
+
+        let start = self.state.next_token.fetch_add(1, Ordering::Relaxed) % tokens.len();
+        let token = (0..tokens.len())
+            .map(|offset| &tokens[(start + offset) % tokens.len()])
+            .find(|token| token.has_remaining_requests())
+            .unwrap_or(&tokens[start]);
+
+        Some(token.clone())
+    }
+
+    /// Get a post by id.
+    ///
+    /// If the API paginates the post's files,
+    /// every page is fetched and the files are concatenated.
+    /// Each page fetch counts against the rate limit.
+    ///
+    /// If the post's `image_count` does not match the number of returned images,
+    /// a warning is logged.
+    /// See [`Post::image_count`] for which is authoritative.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post(&self, id: &str) -> Result<Post, Error> {
+        self.get_post_priority(id, RequestPriority::Normal).await
+    }
+
+    /// Get a post by id, authorizing with the given token instead of the stored tokens.
+    ///
+    /// Calls with the same token share its ratelimit, like [`Client::with_token`].
+    /// Use [`Client::with_token`] to make other requests as a given user.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post_as(&self, id: &str, token: &str) -> Result<Post, Error> {
+        self.with_token(token).get_post(id).await
+    }
+
+    /// Get a post by id from the API, merged with what can be scraped from its page.
+    ///
+    /// The API request and the scrape are made concurrently.
+    /// Scraping is best-effort:
+    /// if it fails, the API post is still returned, along with the scrape error.
+    /// When both sources have a field, the API's is used.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_full_post(&self, id: &str) -> Result<FullPost, Error> {
+        let scrape = tokio::spawn({
+            let client = self.clone();
+            let id = id.to_string();
+            async move {
+                let text = client.fetch_post_html(&id).await?;
+                let post = tokio::task::spawn_blocking(move || ScrapedPost::from_html_str(&text))
+                    .await??;
+                Ok::<_, Error>(post)
+            }
+        });
+        let post = match self.get_post(id).await {
+            Ok(post) => post,
+            Err(error) => {
+                scrape.abort();
+                return Err(error);
+            }
+        };
+
+        Ok(merge_full_post(id, post, scrape.await))
+    }
+
+    /// Get the top-level fields of a post by id.
+    ///
+    /// This skips the post's files while parsing,
+    /// making it cheaper than [`Client::get_post`] for list views.
+    /// Only the first page is requested, as files are not needed.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post_meta(&self, id: &str) -> Result<PostMeta, Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let url = format!("{API_BASE}/v1/post/{id}");
+
+        self.check_known_missing(id)?;
+        token.ratelimit().await;
+
+        let request = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
+        self.record_post_status(id, response.status());
+
+        let response: ApiResponse<PostMeta> = read_api_json(response.error_for_status()?).await?;
+
+        Ok(response.data)
+    }
+
+    /// Get the link to the cover file of a post by id.
+    ///
+    /// The cover is the file with the lowest position, which is the first file shown on the post page.
+    /// Only the first page of files is requested,
+    /// and only the cover's link is kept while parsing them,
+    /// making this cheaper than [`Client::get_post`] for thumbnail grids.
+    /// If the post has no files, [`Error::NoCover`] is returned.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post_cover_url(&self, id: &str) -> Result<String, Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let url = format!("{API_BASE}/v1/post/{id}");
+
+        self.check_known_missing(id)?;
+        token.ratelimit().await;
+
+        let request = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
+        self.record_post_status(id, response.status());
+
+        let response: ApiResponse<ApiPostCover> =
+            read_api_json(response.error_for_status()?).await?;
+
+        response
+            .data
+            .link
+            .map(String::from)
+            .ok_or_else(|| Error::NoCover { id: id.into() })
+    }
+
+    /// Returns true if a post was recently not found.
+    ///
+    /// See [`ClientBuilder::missing_post_ttl`].
+    /// This is always false if remembering missing posts is disabled.
+    pub fn is_known_missing(&self, id: &str) -> bool {
+        let ttl = match self.state.missing_post_ttl {
+            Some(ttl) => ttl,
+            None => return false,
+        };
+
+        self.state
+            .missing_posts
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .get(id)
+            .is_some_and(|missing_since| missing_since.elapsed() < ttl)
+    }
+
+    /// Fail if a post was recently not found.
+    fn check_known_missing(&self, id: &str) -> Result<(), Error> {
+        if self.is_known_missing(id) {
+            return Err(Error::PostKnownMissing { id: id.into() });
+        }
+
+        Ok(())
+    }
+
+    /// Remember a post as missing if it was not found.
+    fn record_post_status(&self, id: &str, status: StatusCode) {
+        if status == StatusCode::NOT_FOUND {
+            self.mark_missing(id);
+        }
+    }
+
+    /// Remember a post as missing.
+    pub(crate) fn mark_missing(&self, id: &str) {
+        let ttl = match self.state.missing_post_ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+
+        let mut missing_posts = self
+            .state
+            .missing_posts
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        // Drop expired entries, so long runs over many dead ids do not grow this forever.
+        missing_posts.retain(|_, missing_since| missing_since.elapsed() < ttl);
+        missing_posts.insert(id.into(), Instant::now());
+    }
+
+    /// Get a post by id, with the given rate limit priority.
+    ///
+    /// If [`ClientBuilder::high_priority_reserve`] is set,
+    /// high priority requests may use that reserve of each window's budget,
+    /// which normal requests leave unused.
+    /// Otherwise, the priority has no effect.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post_priority(
+        &self,
+        id: &str,
+        priority: RequestPriority,
+    ) -> Result<Post, Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let url = format!("{API_BASE}/v1/post/{id}");
+
+        self.check_known_missing(id)?;
+        token.ratelimit_with_priority(priority).await;
+
+        let request = self
+            .client
+            .get(&url)
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
+        self.record_post_status(id, response.status());
+
+        let response: ApiResponse<Post> = read_api_json(response.error_for_status()?).await?;
+        let post = self
+            .get_remaining_post_pages(&token, priority, &url, response)
+            .await?;
+        warn_on_image_count_mismatch(&post);
+
+        Ok(post)
+    }
+
+    /// Fetch the remaining pages of a post's files and append them to the post.
+    pub(crate) async fn get_remaining_post_pages(
+        &self,
+        token: &TokenState,
+        priority: RequestPriority,
+        url: &str,
+        response: ApiResponse<Post>,
+    ) -> Result<Post, Error> {
+        let mut next = next_page_url(url, &response);
+        let mut post = response.data;
+        let mut images = std::mem::take(&mut post.images).into_vec();
+
+        while let Some(next_url) = next {
+            if u64::try_from(images.len()).unwrap() >= post.image_count {
+                break;
+            }
+
+            token.ratelimit_with_priority(priority).await;
+
+            let request = self
+                .client
+                .get(next_url)
+                .header(AUTHORIZATION, format!("Bearer {}", token.value));
+            let response = self.send(Endpoint::Post, request).await?;
+            let response: ApiResponse<Post> = read_api_json(response.error_for_status()?).await?;
+
+            next = next_page_url(url, &response);
+            // Guard against a server that keeps returning empty pages.
+            if response.data.images.is_empty() {
+                break;
+            }
+            images.extend(response.data.images.into_vec());
+        }
+
+        post.images = images.into();
+
+        Ok(post)
+    }
+
+    /// Get a post by id, unless it has not changed since it was cached.
+    ///
+    /// If `cached` is provided, its validators are sent with the request.
+    /// Returns `None` if the server reports that the post was not modified.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn get_post_conditional(
+        &self,
+        id: &str,
+        cached: Option<&CachedPost>,
+    ) -> Result<Option<CachedPost>, Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let url = format!("{API_BASE}/v1/post/{id}");
+
+        let mut request = self
+            .client
+            .get(&url)
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        if let Some(etag) = cached.and_then(|cached| cached.etag.as_deref()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = cached.and_then(|cached| cached.last_modified.as_deref()) {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        self.check_known_missing(id)?;
+        token.ratelimit().await;
+
+        let response = self.send(Endpoint::Post, request).await?;
+        self.record_post_status(id, response.status());
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+        let get_header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(Box::<str>::from)
+        };
+        let etag = get_header(ETAG);
+        let last_modified = get_header(LAST_MODIFIED);
+
+        let response: ApiResponse<Post> = read_api_json(response).await?;
+        let post = self
+            .get_remaining_post_pages(&token, RequestPriority::Normal, &url, response)
+            .await?;
+        warn_on_image_count_mismatch(&post);
+
+        Ok(Some(CachedPost {
+            post,
+            etag,
+            last_modified,
+        }))
+    }
+
+    /// Check that a file has an extension that is accepted for uploads.
+    ///
+    /// This is done automatically when uploading.
+    /// The accepted extensions are a best-effort list,
+    /// see [`ClientBuilder::upload_extensions`].
+    pub fn validate_upload(&self, file: &UploadPostFile) -> Result<(), Error> {
+        let is_supported = file
+            .file_name
+            .rsplit_once('.')
+            .is_some_and(|(_, extension)| {
+                self.state
+                    .upload_extensions
+                    .iter()
+                    .any(|supported| supported.eq_ignore_ascii_case(extension))
+            });
+
+        if !is_supported {
+            return Err(Error::UnsupportedFormat {
+                file_name: file.file_name.as_str().into(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the file name to upload a file as.
+    fn upload_file_name(&self, file_name: String) -> String {
+        if self.state.sanitize_file_names {
+            sanitize_file_name(&file_name)
+        } else {
+            file_name
+        }
+    }
+
+    /// Check that an upload request does not have more images than the configured maximum.
+    fn validate_image_count(&self, provided: usize) -> Result<(), Error> {
+        let limit = self.state.max_images_per_request;
+        if provided > limit {
+            return Err(Error::TooManyImages { limit, provided });
+        }
+
+        Ok(())
+    }
+
+    /// Check that a description is not longer than the configured maximum.
+    fn validate_description_length(&self, description: &str) -> Result<(), Error> {
+        if let Some(max) = self.state.max_description_length {
+            let actual = description.chars().count();
+            if actual > max {
+                return Err(Error::DescriptionTooLong { max, actual });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a post.
+    ///
+    /// This completes once the server has finished processing every upload.
+    /// The returned post is complete, and its `id` can be used immediately.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn create_post(&self, data: CreatePostBuilder) -> Result<Post, Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let request = self.create_post_request(&token.value, data)?;
+
+        token.ratelimit().await;
+
+        let response = self.send(Endpoint::Post, request).await?;
+
+        if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        let post: ApiResponse<_> = read_api_json(response.error_for_status()?).await?;
+
+        Ok(post.data)
+    }
+
+    /// Build the request to create a post.
+    ///
+    /// This must be a multipart form, as it contains the uploaded files.
+    pub(crate) fn create_post_request(
+        &self,
+        token: &str,
+        mut data: CreatePostBuilder,
+    ) -> Result<reqwest::RequestBuilder, Error> {
         let url = format!("{API_BASE}/v1/post");
 
+        data.apply_descriptions_by_filename()?;
+
+        if self.state.require_explicit_privacy && data.privacy.is_none() {
+            return Err(Error::PrivacyNotSpecified);
+        }
+
         let mut form = Form::new();
 
         if let Some(title) = data.title {
@@ -329,34 +1972,173 @@ impl Client {
         if data.images.is_empty() {
             return Err(Error::MissingImages);
         }
+        self.validate_image_count(data.images.len())?;
 
-        for file in data.images {
-            let part = reqwest::multipart::Part::stream(file.body).file_name(file.file_name);
+        for file in data.images.iter() {
+            self.validate_upload(file)?;
+            if let Some(description) = file.description.as_deref() {
+                self.validate_description_length(description)?;
+            }
+        }
 
-            form = form.part("images[]", part);
+        // Descriptions are matched to images by index,
+        // so images without one still need an empty entry.
+        for description in upload_descriptions(&data.images).into_iter().flatten() {
+            form = form.text(DESCRIPTIONS_FIELD, description.to_string());
         }
 
-        self.state.ratelimit().await;
+        for file in data.images {
+            let file_name = self.upload_file_name(file.file_name);
+            let part = reqwest::multipart::Part::stream(file.body).file_name(file_name);
+
+            form = form.part(IMAGES_FIELD, part);
+        }
 
-        let response = self
+        Ok(self
             .client
             .post(url)
             .header(AUTHORIZATION, format!("Bearer {token}"))
-            .multipart(form)
-            .send()
-            .await?;
+            .multipart(form))
+    }
+
+    /// Create a post with any number of images.
+    ///
+    /// The post is created with the first batch of images,
+    /// then the rest are added with [`Client::add_post_images`] one batch at a time.
+    /// The batch size defaults to [`ClientBuilder::max_images_per_request`].
+    ///
+    /// Only the first batch can have descriptions, as [`Client::add_post_images`] cannot send them.
+    /// If a later image has one, [`Error::DescriptionsNotSupported`] is returned before anything is uploaded.
+    /// Set them with [`Client::update_files_bulk`] after the post is created instead.
+    ///
+    /// Batches are sent in order, one at a time, and the server appends each batch to the post,
+    /// so the positions of the returned post's files match the order of the input images.
+    /// This is checked against the original names of the files once all batches are added.
+    /// The API has no way to reorder files, so if the order does not match, a warning is logged.
+    /// The server may also normalize the names, which makes them look out of order.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn create_large_post(
+        &self,
+        data: CreatePostBuilder,
+        batch_size: Option<NonZeroUsize>,
+    ) -> Result<Post, Error> {
+        self.create_large_post_with_progress(data, batch_size, |_post, _uploaded| {})
+            .await
+    }
+
+    /// Create a post with any number of images, reporting progress after each batch.
+    ///
+    /// This is like [`Client::create_large_post`],
+    /// but `on_batch` is called with the post and the number of images uploaded so far
+    /// each time a batch is uploaded, starting with the batch that creates the post.
+    /// If a later batch fails, the post still exists with the images reported so far,
+    /// so the post id can be used to add the rest.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn create_large_post_with_progress<F>(
+        &self,
+        mut data: CreatePostBuilder,
+        batch_size: Option<NonZeroUsize>,
+        mut on_batch: F,
+    ) -> Result<Post, Error>
+    where
+        F: FnMut(&Post, usize),
+    {
+        let batch_size = batch_size.map_or(self.state.max_images_per_request, NonZeroUsize::get);
+
+        // Descriptions can only be sent with the first batch,
+        // so match them up front to find any that are in later batches.
+        data.apply_descriptions_by_filename()?;
+        if data
+            .images
+            .iter()
+            .skip(batch_size)
+            .any(|file| file.description.is_some())
+        {
+            return Err(Error::DescriptionsNotSupported);
+        }
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let file_names: Vec<_> = data
+            .images
+            .iter()
+            .map(|file| self.upload_file_name(file.file_name.clone()))
+            .collect();
 
-        Ok(post.data)
+        let mut images = std::mem::take(&mut data.images).into_iter();
+        data.images.extend(images.by_ref().take(batch_size));
+
+        let mut uploaded = data.images.len();
+        let mut post = self.create_post(data).await?;
+        on_batch(&post, uploaded);
+
+        let id = post.id.clone();
+        loop {
+            let batch: Vec<_> = images.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                break;
+            }
+
+            uploaded += batch.len();
+            post = self.add_post_images(&id, batch).await?;
+            on_batch(&post, uploaded);
+        }
+
+        if let Some(index) = find_upload_order_mismatch(&post.images, &file_names) {
+            tracing::warn!(
+                id = &*id,
+                index,
+                "the files of the post may not match the upload order"
+            );
+        }
+
+        Ok(post)
     }
 
     /// Update a post.
     ///
+    /// The server may accept an update without applying all of its fields.
+    /// If the returned post does not reflect a requested change, a warning is logged.
+    /// See [`UpdatePostBuilder::unapplied_fields`] to check this manually.
+    ///
+    /// The server may also trim or truncate the title,
+    /// so the returned post's title can differ from the one that was sent.
+    /// The returned post is the server's canonical version,
+    /// and [`UpdatePostBuilder::title_outcome`] reports how the title changed.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn update_post(&self, id: &str, data: UpdatePostBuilder) -> Result<Post, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
+        let request = self.update_post_request(&token.value, id, &data)?;
+
+        token.ratelimit().await;
+
+        let response = self.send(Endpoint::Post, request).await?;
+        let post: ApiResponse<Post> = read_api_json(response.error_for_status()?).await?;
+        let post = post.data;
+
+        let unapplied_fields = data.unapplied_fields(&post);
+        if !unapplied_fields.is_empty() {
+            tracing::warn!(
+                id,
+                ?unapplied_fields,
+                "post update was accepted, but some fields were not applied"
+            );
+        }
+
+        Ok(post)
+    }
+
+    /// Build the request to update a post.
+    pub(crate) fn update_post_request(
+        &self,
+        token: &str,
+        id: &str,
+        data: &UpdatePostBuilder,
+    ) -> Result<reqwest::RequestBuilder, Error> {
         let url = format!("{API_BASE}/v1/post/{id}");
 
         let mut form = Vec::new();
@@ -377,22 +2159,14 @@ impl Client {
             form.push(("nsfw", bool_to_str(nsfw)));
         }
 
-        self.state.ratelimit().await;
-
         // Not using a multipart form here is intended.
         // Even though we use a multipart form for creating a post,
         // the server will silently ignore requests that aren't form-urlencoded.
-        let response = self
+        Ok(self
             .client
             .patch(url)
             .header(AUTHORIZATION, format!("Bearer {token}"))
-            .form(&form)
-            .send()
-            .await?;
-
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
-
-        Ok(post.data)
+            .form(&form))
     }
 
     /// Delete a post.
@@ -403,16 +2177,47 @@ impl Client {
         let token = self.get_token().ok_or(Error::MissingToken)?;
         let url = format!("{API_BASE}/v1/post/{id}");
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .delete(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
+
+        let response: ApiCompletedResponse = read_api_json(response.error_for_status()?).await?;
+        if !response.success {
+            return Err(Error::ApiOperationFailed);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a post using its [`Post::delete_url`].
+    ///
+    /// The delete url is only present on posts owned by the current user.
+    /// The url must point at the API host, or an error is returned without making a request.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn delete_post_by_url(&self, delete_url: &str) -> Result<(), Error> {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+
+        let url = reqwest::Url::parse(delete_url).map_err(|_| Error::InvalidDeleteUrl)?;
+        let api_base = reqwest::Url::parse(API_BASE).expect("invalid api base");
+        if url.scheme() != api_base.scheme() || url.host_str() != api_base.host_str() {
+            return Err(Error::InvalidDeleteUrl);
+        }
+
+        token.ratelimit().await;
+
+        let request = self
+            .client
+            .delete(url)
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = read_api_json(response.error_for_status()?).await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -420,6 +2225,19 @@ impl Client {
         Ok(())
     }
 
+    /// Delete a post, returning a snapshot of it taken just before deletion.
+    ///
+    /// This makes an extra request to fetch the post before deleting it.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn delete_post_returning(&self, id: &str) -> Result<Post, Error> {
+        let post = self.get_post(id).await?;
+        self.delete_post(id).await?;
+
+        Ok(post)
+    }
+
     /// Favorite or unfavorite a post.
     ///
     /// # Returns
@@ -432,16 +2250,15 @@ impl Client {
         let token = self.get_token().ok_or(Error::MissingToken)?;
         let url = format!("{API_BASE}/v1/post/{id}/favorite");
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .post(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::Post, request).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = read_api_json(response.error_for_status()?).await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -454,8 +2271,46 @@ impl Client {
         }
     }
 
+    /// Ensure a post is favorited.
+    ///
+    /// The API only offers a toggle, so if the post was already favorited,
+    /// it is briefly unfavorited and then favorited again.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn add_favorite(&self, id: &str) -> Result<(), Error> {
+        if !self.favorite_post(id).await? {
+            self.favorite_post(id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Ensure a post is not favorited.
+    ///
+    /// The API only offers a toggle, so if the post was not favorited,
+    /// it is briefly favorited and then unfavorited again.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn remove_favorite(&self, id: &str) -> Result<(), Error> {
+        if self.favorite_post(id).await? {
+            self.favorite_post(id).await?;
+        }
+
+        Ok(())
+    }
+
     /// Add images to a post.
     ///
+    /// The API does not accept descriptions when adding images,
+    /// so images with a description are rejected with [`Error::DescriptionsNotSupported`]
+    /// instead of silently losing it.
+    /// Set them with [`Client::update_files_bulk`] after adding the images instead.
+    ///
+    /// If [`ClientBuilder::max_files_per_post`] is set,
+    /// the post is fetched first to check that the limit would not be exceeded.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn add_post_images<I>(&self, id: &str, images: I) -> Result<Post, Error>
@@ -467,51 +2322,70 @@ impl Client {
 
         let mut form = Form::new();
 
-        let mut num_images = 0;
+        let mut num_images: u64 = 0;
         for file in images {
-            let part = reqwest::multipart::Part::stream(file.body).file_name(file.file_name);
+            self.validate_upload(&file)?;
+            if file.description.is_some() {
+                return Err(Error::DescriptionsNotSupported);
+            }
+
+            let file_name = self.upload_file_name(file.file_name);
+            let part = reqwest::multipart::Part::stream(file.body).file_name(file_name);
 
-            form = form.part("images[]", part);
+            form = form.part(IMAGES_FIELD, part);
             num_images += 1;
         }
 
         if num_images == 0 {
             return Err(Error::MissingImages);
         }
+        self.validate_image_count(usize::try_from(num_images).unwrap())?;
+
+        if let Some(limit) = self.state.max_files_per_post {
+            let post = self.get_post(id).await?;
+            let would_be = post.image_count + num_images;
+            if would_be > limit {
+                return Err(Error::PostFileLimitExceeded { limit, would_be });
+            }
+        }
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .post(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .multipart(form)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value))
+            .multipart(form);
+        let response = self.send(Endpoint::Post, request).await?;
+
+        if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(Error::PayloadTooLarge);
+        }
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let post: ApiResponse<_> = read_api_json(response.error_for_status()?).await?;
 
         Ok(post.data)
     }
 
     /// Get a user by username.
     ///
+    /// See [`Client::get_scraped_user`] for a tokenless alternative.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn get_user(&self, username: &str) -> Result<User, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
         let url = format!("{API_BASE}/v1/user/{username}");
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::User, request).await?;
 
-        let user: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let user: ApiResponse<_> = read_api_json(response.error_for_status()?).await?;
 
         Ok(user.data)
     }
@@ -522,6 +2396,9 @@ impl Client {
     /// but the API will always return no data for some reason.
     /// It is likely that this endpoint is disabled.
     /// As a result, this function is currently useless.
+    /// A response with no data is returned as [`Error::EmptyResponse`].
+    ///
+    /// There is also no known way to find the post a file belongs to from its id alone.
     ///
     /// # Authorization
     /// This function REQUIRES a token.
@@ -529,22 +2406,26 @@ impl Client {
         let token = self.get_token().ok_or(Error::MissingToken)?;
         let url = format!("{API_BASE}/v1/file/{id}");
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::File, request).await?;
 
-        let file: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let file: ApiResponse<Option<PostFile>> =
+            read_api_json(response.error_for_status()?).await?;
 
-        Ok(file.data)
+        file.data.ok_or(Error::EmptyResponse)
     }
 
     /// Update a file.
     ///
+    /// The description must not be empty,
+    /// as the API treats it as non-nullable.
+    /// There is no known way to clear a file's description.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn update_file(&self, id: &str, description: &str) -> Result<(), Error> {
@@ -554,18 +2435,18 @@ impl Client {
         if description.is_empty() {
             return Err(Error::MissingDescription);
         }
+        self.validate_description_length(description)?;
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .patch(url)
             .form(&[("description", description)])
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::File, request).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = read_api_json(response.error_for_status()?).await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -581,16 +2462,15 @@ impl Client {
         let token = self.get_token().ok_or(Error::MissingToken)?;
         let url = format!("{API_BASE}/v1/file/{id}");
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .delete(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value));
+        let response = self.send(Endpoint::File, request).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = read_api_json(response.error_for_status()?).await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -598,7 +2478,49 @@ impl Client {
         Ok(())
     }
 
+    /// Delete the files at the given positions of a post.
+    ///
+    /// The post is fetched once to find the files at the positions.
+    /// If any position does not exist, nothing is deleted.
+    /// The API has no bulk delete endpoint,
+    /// so files are deleted one at a time, as the rate limit would serialize them anyway.
+    /// If a deletion fails, the files after it are not deleted.
+    ///
+    /// Returns the deleted files, in position order.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn delete_post_files_by_positions(
+        &self,
+        post_id: &str,
+        positions: &[NonZeroU32],
+    ) -> Result<Vec<PostFile>, Error> {
+        let post = self.get_post(post_id).await?;
+
+        let mut positions = positions.to_vec();
+        positions.sort();
+        positions.dedup();
+
+        let mut images = post.images.into_vec();
+        let mut files = Vec::with_capacity(positions.len());
+        for position in positions {
+            let index = images
+                .iter()
+                .position(|file| file.position == position)
+                .ok_or(Error::InvalidPosition { position })?;
+            files.push(images.swap_remove(index));
+        }
+
+        for file in files.iter() {
+            self.delete_file(&file.id).await?;
+        }
+
+        Ok(files)
+    }
+
     /// Update files in bulk.
+    ///
+    /// The returned files are in the same order as the input updates.
     pub async fn update_files_bulk<I>(&self, files: I) -> Result<Vec<PostFile>, Error>
     where
         I: IntoIterator<Item = FileUpdate>,
@@ -612,24 +2534,30 @@ impl Client {
                 if file.description.is_empty() {
                     return Err(Error::MissingDescription);
                 }
+                self.validate_description_length(&file.description)?;
                 Ok(file)
             })
             .collect::<Result<Vec<_>, _>>()?;
+        if data.is_empty() {
+            return Err(Error::NoUpdates);
+        }
+        let ids: Vec<_> = data.iter().map(|file| file.id.clone()).collect();
         let data = ApiUpdateFilesBulkRequest { data };
 
-        self.state.ratelimit().await;
+        token.ratelimit().await;
 
-        let response = self
+        let request = self
             .client
             .patch(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .json(&data)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {}", token.value))
+            .json(&data);
+        let response = self.send(Endpoint::File, request).await?;
 
-        let file: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let file: ApiResponse<Vec<PostFile>> = read_api_json(response.error_for_status()?).await?;
+        let mut files = file.data;
+        sort_files_by_ids(&mut files, &ids);
 
-        Ok(file.data)
+        Ok(files)
     }
 }
 
@@ -639,23 +2567,192 @@ impl Default for Client {
     }
 }
 
-#[derive(Debug)]
 struct ClientState {
-    token: std::sync::RwLock<Option<Arc<str>>>,
-    ratelimit_data: std::sync::Mutex<(Instant, u8)>,
+    tokens: std::sync::RwLock<Vec<Arc<TokenState>>>,
+    next_token: AtomicUsize,
+    max_scrape_body_size: u64,
+    download_throttle: Option<Throttle>,
+    upload_extensions: Box<[String]>,
+    in_flight_scrapes: std::sync::Mutex<HashMap<Box<str>, InFlightScrape>>,
+    metrics: Metrics,
+    max_files_per_post: Option<u64>,
+    max_description_length: Option<usize>,
+    scrape_timeout: Option<Duration>,
+    require_explicit_privacy: bool,
+    sanitize_file_names: bool,
+    missing_post_ttl: Option<Duration>,
+    max_images_per_request: usize,
+    clock: Arc<dyn Clock>,
+    missing_posts: std::sync::Mutex<HashMap<Box<str>, Instant>>,
+    override_tokens: std::sync::Mutex<HashMap<Box<str>, Arc<TokenState>>>,
+    cdn_base: Option<reqwest::Url>,
+    interceptors: Box<[Arc<dyn RequestInterceptor>]>,
+    allow_nsfw: bool,
+    rate_limit: RateLimit,
 }
 
 impl ClientState {
-    fn new() -> Self {
-        let now = Instant::now();
+    fn new(builder: &ClientBuilder, cdn_base: Option<reqwest::Url>) -> Self {
+        Self {
+            tokens: std::sync::RwLock::new(Vec::new()),
+            next_token: AtomicUsize::new(0),
+            max_scrape_body_size: builder.max_scrape_body_size,
+            download_throttle: builder
+                .max_download_bytes_per_second
+                .and_then(NonZeroU64::new)
+                .map(Throttle::new),
+            upload_extensions: builder.upload_extensions.clone().into(),
+            in_flight_scrapes: std::sync::Mutex::new(HashMap::new()),
+            metrics: Metrics::default(),
+            max_files_per_post: builder.max_files_per_post,
+            max_description_length: builder.max_description_length,
+            scrape_timeout: builder.scrape_timeout,
+            require_explicit_privacy: builder.require_explicit_privacy,
+            sanitize_file_names: builder.sanitize_file_names,
+            missing_post_ttl: builder.missing_post_ttl,
+            max_images_per_request: builder.max_images_per_request,
+            clock: builder.clock.clone(),
+            missing_posts: std::sync::Mutex::new(HashMap::new()),
+            override_tokens: std::sync::Mutex::new(HashMap::new()),
+            cdn_base,
+            interceptors: builder.interceptors.clone().into(),
+            allow_nsfw: builder.allow_nsfw,
+            rate_limit: RateLimit::new(
+                builder.requests_per_minute,
+                builder.rate_limit_window,
+                builder.high_priority_reserve,
+            ),
+        }
+    }
+}
+
+impl std::fmt::Debug for ClientState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tokens = self
+            .tokens
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+
+        f.debug_struct("ClientState")
+            .field("tokens", &*tokens)
+            .field("next_token", &self.next_token)
+            .field("max_scrape_body_size", &self.max_scrape_body_size)
+            .field("download_throttle", &self.download_throttle)
+            .field("upload_extensions", &self.upload_extensions)
+            .field("interceptors", &self.interceptors)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The ratelimit of each token.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RateLimit {
+    /// The number of requests per window, or 0 if unlimited.
+    requests: u32,
+
+    /// The length of a window, or zero if unlimited.
+    window: Duration,
+
+    /// The number of requests per window that only high priority requests may use.
+    high_priority_reserve: u32,
+}
+
+impl RateLimit {
+    pub(crate) fn new(requests: u32, window: Duration, high_priority_reserve: u32) -> Self {
+        Self {
+            requests,
+            window,
+            // Leave at least 1 request for normal requests.
+            high_priority_reserve: high_priority_reserve.min(requests.saturating_sub(1)),
+        }
+    }
+
+    /// Returns true if requests never wait.
+    fn is_unlimited(&self) -> bool {
+        self.requests == 0 || self.window.is_zero()
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new(REQUESTS_PER_MINUTE, ONE_MINUTE, 0)
+    }
+}
+
+/// A token and its ratelimit.
+pub(crate) struct TokenState {
+    value: Arc<str>,
+    ratelimit_data: std::sync::Mutex<(Instant, u32)>,
+    rate_limit: RateLimit,
+    clock: Arc<dyn Clock>,
+}
+
+impl TokenState {
+    pub(crate) fn new<T>(value: T, clock: Arc<dyn Clock>, rate_limit: RateLimit) -> Self
+    where
+        T: AsRef<str>,
+    {
+        let now = clock.now();
 
         Self {
-            token: std::sync::RwLock::new(None),
-            ratelimit_data: std::sync::Mutex::new((now, REQUESTS_PER_MINUTE)),
+            value: value.as_ref().into(),
+            ratelimit_data: std::sync::Mutex::new((now, rate_limit.requests)),
+            rate_limit,
+            clock,
+        }
+    }
+
+    /// Refill the ratelimit and start a new window.
+    pub(crate) fn reset_ratelimit(&self) {
+        *self
+            .ratelimit_data
+            .lock()
+            .expect("ratelimit mutex poisoned") = (self.clock.now(), self.rate_limit.requests);
+    }
+
+    /// Returns true if the current window is over, so the next request starts a new one.
+    pub(crate) fn is_window_over(&self) -> bool {
+        if self.rate_limit.is_unlimited() {
+            return true;
+        }
+
+        let (last_refreshed, _) = *self
+            .ratelimit_data
+            .lock()
+            .expect("ratelimit mutex poisoned");
+        self.clock.now().saturating_duration_since(last_refreshed) >= self.rate_limit.window
+    }
+
+    /// Returns true if a normal priority request could be made with this token without waiting.
+    pub(crate) fn has_remaining_requests(&self) -> bool {
+        if self.rate_limit.is_unlimited() {
+            return true;
         }
+
+        let ratelimit_data = self
+            .ratelimit_data
+            .lock()
+            .expect("ratelimit mutex poisoned");
+        let (last_refreshed, remaining_requests) = *ratelimit_data;
+
+        remaining_requests > self.rate_limit.high_priority_reserve
+            || self.clock.now().saturating_duration_since(last_refreshed) >= self.rate_limit.window
+    }
+
+    pub(crate) async fn ratelimit(&self) {
+        self.ratelimit_with_priority(RequestPriority::Normal).await
     }
 
-    async fn ratelimit(&self) {
+    pub(crate) async fn ratelimit_with_priority(&self, priority: RequestPriority) {
+        if self.rate_limit.is_unlimited() {
+            return;
+        }
+
+        let reserve = match priority {
+            RequestPriority::Normal => self.rate_limit.high_priority_reserve,
+            RequestPriority::High => 0,
+        };
+
         loop {
             let sleep_duration = {
                 let mut ratelimit_data = self
@@ -664,26 +2761,294 @@ impl ClientState {
                     .expect("ratelimit mutex poisoned");
                 let (ref mut last_refreshed, ref mut remaining_requests) = &mut *ratelimit_data;
 
-                // Refresh the number of requests each minute.
-                if last_refreshed.elapsed() >= ONE_MINUTE {
-                    *last_refreshed = Instant::now();
-                    *remaining_requests = REQUESTS_PER_MINUTE;
+                // Refresh the number of requests each window.
+                let now = self.clock.now();
+                if now.saturating_duration_since(*last_refreshed) >= self.rate_limit.window {
+                    *last_refreshed = now;
+                    *remaining_requests = self.rate_limit.requests;
                 }
 
                 // If we are allowed to make a request now, make it.
-                if *remaining_requests > 0 {
+                if *remaining_requests > reserve {
                     *remaining_requests -= 1;
                     return;
                 }
 
                 // Otherwise, sleep until the next refresh and try again.
-                ONE_MINUTE.saturating_sub(last_refreshed.elapsed())
+                self.rate_limit
+                    .window
+                    .saturating_sub(now.saturating_duration_since(*last_refreshed))
             };
-            tokio::time::sleep(sleep_duration).await;
+            self.clock.sleep(sleep_duration).await;
+        }
+    }
+}
+
+impl std::fmt::Debug for TokenState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the token.
+        f.debug_struct("TokenState")
+            .field("value", &Redacted)
+            .field("ratelimit_data", &self.ratelimit_data)
+            .field("rate_limit", &self.rate_limit)
+            .finish()
+    }
+}
+
+/// A scrape that is in progress.
+///
+/// This will be `None` until the request completes,
+/// then `Some(None)` if it failed.
+type InFlightScrape = tokio::sync::watch::Receiver<Option<Option<ScrapedPost>>>;
+
+/// Removes an in-flight scrape when dropped.
+struct InFlightScrapeGuard<'a> {
+    state: &'a ClientState,
+    id: &'a str,
+}
+
+impl Drop for InFlightScrapeGuard<'_> {
+    fn drop(&mut self) {
+        self.state
+            .in_flight_scrapes
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .remove(self.id);
+    }
+}
+
+/// A placeholder for a secret value in debug output.
+struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Merge a post from the API with the result of scraping its page.
+///
+/// A scrape task that panicked or was cancelled is reported as a scrape error.
+pub(crate) fn merge_full_post(
+    id: &str,
+    post: Post,
+    scraped: Result<Result<ScrapedPost, Error>, tokio::task::JoinError>,
+) -> FullPost {
+    let (user_id, scrape_error) = match scraped.map_err(Error::from).and_then(|scraped| scraped) {
+        Ok(scraped) => (scraped.user_id, None),
+        Err(error) => {
+            tracing::warn!(id, "failed to scrape post page: {error}");
+            (None, Some(error))
+        }
+    };
+
+    FullPost {
+        post,
+        user_id,
+        scrape_error,
+    }
+}
+
+/// Log a warning if a post's image count does not match its images.
+fn warn_on_image_count_mismatch(post: &Post) {
+    let num_images = u64::try_from(post.images.len()).unwrap();
+    if post.image_count != num_images {
+        tracing::warn!(
+            id = &*post.id,
+            image_count = post.image_count,
+            num_images,
+            "post image count does not match the number of images"
+        );
+    }
+}
+
+/// Sanitize a file name for uploading.
+///
+/// See [`ClientBuilder::sanitize_file_names`] for the rules.
+pub(crate) fn sanitize_file_name(file_name: &str) -> String {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+    let sanitized: String = file_name
+        .chars()
+        .map(|ch| {
+            if ch.is_alphanumeric() || matches!(ch, '-' | '_' | '.') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let (stem, extension) = sanitized
+        .rsplit_once('.')
+        .unwrap_or((sanitized.as_str(), ""));
+    let stem = stem.trim_start_matches('.');
+    let stem = if stem.is_empty() { "file" } else { stem };
+
+    if extension.is_empty() {
+        stem.to_string()
+    } else {
+        format!("{stem}.{extension}")
+    }
+}
+
+/// Report a page error from scraping a user as a user error.
+fn into_user_error(error: Error) -> Error {
+    match error {
+        Error::InvalidScrapedPost(error) => Error::InvalidScrapedUser(error),
+        error => error,
+    }
+}
+
+/// Get the charset parameter of a `Content-Type` header value, without quotes.
+pub(crate) fn content_type_charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Decode the body of a scraped page.
+///
+/// The body is always decoded as UTF-8, whatever charset the page declares.
+/// Invalid bytes are replaced instead of failing the whole page.
+pub(crate) fn decode_scrape_body(body: Vec<u8>) -> String {
+    match String::from_utf8(body) {
+        Ok(body) => body,
+        Err(error) => {
+            tracing::warn!(
+                "scraped page is not valid UTF-8 at byte {}, decoding lossily",
+                error.utf8_error().valid_up_to()
+            );
+            String::from_utf8_lossy(error.as_bytes()).into_owned()
         }
     }
 }
 
+/// Read the json body of a successful api response.
+///
+/// The body is read fully first, so that a body that does not have the expected shape
+/// can be returned in the error.
+pub(crate) async fn read_api_json<T>(response: reqwest::Response) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let body = response.bytes().await?;
+    parse_api_json(&body)
+}
+
+/// Parse the json body of a successful api response.
+pub(crate) fn parse_api_json<T>(body: &[u8]) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_json::from_slice(body).map_err(|error| Error::UnexpectedResponseShape {
+        body: String::from_utf8_lossy(body).into_owned(),
+        error,
+    })
+}
+
+/// Get the url of the next page of a paginated response.
+///
+/// `url` is the url of the first page.
+/// The server's `next` link is only followed if it has the same scheme, host, and port as `url`,
+/// as the token is sent with it.
+/// Otherwise, pagination stops.
+pub(crate) fn next_page_url<T>(url: &str, response: &ApiResponse<T>) -> Option<String> {
+    let base = reqwest::Url::parse(url).ok()?;
+
+    if let Some(next) = response
+        .links
+        .as_ref()
+        .and_then(|links| links.next.as_deref())
+    {
+        let next = match base.join(next) {
+            Ok(next) if next.origin() == base.origin() => next,
+            _ => {
+                tracing::warn!(next, "not following a next page link to another origin");
+                return None;
+            }
+        };
+        return Some(next.into());
+    }
+
+    let meta = response.meta.as_ref()?;
+    if meta.current_page >= meta.last_page {
+        return None;
+    }
+
+    let query: Vec<_> = base
+        .query_pairs()
+        .filter(|(key, _)| key != "page")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let mut next = base;
+    next.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("page", &(meta.current_page + 1).to_string());
+
+    Some(next.into())
+}
+
+/// Sort files to match the order of the given ids.
+///
+/// Files with ids that are not present are moved to the end.
+pub(crate) fn sort_files_by_ids(files: &mut [PostFile], ids: &[String]) {
+    files.sort_by_key(|file| {
+        ids.iter()
+            .position(|id| **id == *file.id)
+            .unwrap_or(ids.len())
+    });
+}
+
+/// Find the first index where the files of a post, in position order,
+/// do not match the names of the files that were uploaded, in order.
+///
+/// Files are compared by their original names.
+/// If any file is missing its original name, the order cannot be checked and this returns `None`.
+pub(crate) fn find_upload_order_mismatch(
+    files: &[PostFile],
+    file_names: &[String],
+) -> Option<usize> {
+    let mut files: Vec<_> = files.iter().collect();
+    files.sort_by_key(|file| file.position);
+
+    let original_names = files
+        .iter()
+        .map(|file| file.original_name.as_deref())
+        .collect::<Option<Vec<_>>>()?;
+
+    let mismatch = original_names
+        .iter()
+        .zip(file_names)
+        .position(|(original_name, file_name)| *original_name != file_name);
+    if mismatch.is_some() || original_names.len() == file_names.len() {
+        return mismatch;
+    }
+
+    // One list is a prefix of the other.
+    Some(original_names.len().min(file_names.len()))
+}
+
+/// Get the positional descriptions for a list of uploads.
+///
+/// Files without a description get an empty one.
+/// Returns `None` if no file has a description.
+pub(crate) fn upload_descriptions(files: &[UploadPostFile]) -> Option<Vec<&str>> {
+    if files.iter().all(|file| file.description.is_none()) {
+        return None;
+    }
+
+    Some(
+        files
+            .iter()
+            .map(|file| file.description.as_deref().unwrap_or(""))
+            .collect(),
+    )
+}
+
 fn bool_to_str(b: bool) -> &'static str {
     if b {
         "true"
@@ -0,0 +1,266 @@
+use super::manifest::HashWriter;
+use super::Client;
+use super::DownloadSummary;
+use crate::Error;
+use crate::FileKind;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// The progress of an archival job, saved so that the job can be resumed.
+///
+/// The progress file has one json line per completed post,
+/// so each post is recorded by appending to it instead of rewriting it.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveProgress {
+    /// The posts that were completely downloaded, in the order they completed
+    pub posts: Vec<ArchivedPost>,
+}
+
+/// A post that was completely downloaded as part of an archival job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPost {
+    /// The post id
+    pub id: Box<str>,
+
+    /// The files of the post
+    pub files: Vec<ArchivedFile>,
+}
+
+/// A file of an [`ArchivedPost`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    /// The file id
+    pub id: Box<str>,
+
+    /// The name of the file in the post's directory
+    pub file_name: Box<str>,
+
+    /// The sha256 hash of the file when it was downloaded, as lowercase hex
+    ///
+    /// This is informational, for auditing an archive later.
+    /// It is not checked when a job is resumed.
+    pub sha256: String,
+}
+
+impl ArchiveProgress {
+    /// Load progress from a file.
+    ///
+    /// A missing file is treated as a job with no progress.
+    /// An incomplete last line, left by an interrupted append, is ignored,
+    /// so that post is downloaded again.
+    pub async fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data = match tokio::fs::read_to_string(path).await {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut posts = Vec::new();
+        let mut lines = data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        while let Some(line) = lines.next() {
+            match serde_json::from_str(line) {
+                Ok(post) => posts.push(post),
+                Err(error) if lines.peek().is_none() && !data.ends_with('\n') => {
+                    tracing::warn!("ignoring incomplete last line of archive progress: {error}");
+                }
+                Err(error) => return Err(std::io::Error::from(error).into()),
+            }
+        }
+
+        Ok(Self { posts })
+    }
+
+    /// Save progress to a file.
+    ///
+    /// The file is first written next to the given path with a `.part` extension,
+    /// then renamed, so an interruption never leaves a partial progress file.
+    pub async fn save<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut data = Vec::new();
+        for post in self.posts.iter() {
+            serde_json::to_writer(&mut data, post).map_err(std::io::Error::from)?;
+            data.push(b'\n');
+        }
+
+        let mut temp_path = path.as_os_str().to_os_string();
+        temp_path.push(".part");
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(&temp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// Record a completed post by appending it to a progress file.
+    async fn append<P>(path: P, post: &ArchivedPost) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut line = serde_json::to_vec(post).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+
+    /// Returns true if a post was completely downloaded.
+    ///
+    /// This scans every post, so index the ids instead when checking many.
+    pub fn is_completed(&self, id: &str) -> bool {
+        self.posts.iter().any(|post| &*post.id == id)
+    }
+}
+
+/// The result of an archival job.
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    /// The summaries of the posts that were downloaded in this run
+    pub downloaded: Vec<DownloadSummary>,
+
+    /// The number of posts that were skipped because an earlier run completed them
+    pub skipped: usize,
+
+    /// The posts that could not be fetched or downloaded, with their errors
+    pub failed: Vec<(Box<str>, Error)>,
+}
+
+impl Client {
+    /// Download many posts, each into a directory named after its id inside `out_dir`.
+    ///
+    /// Posts are fetched like [`Client::fetch_and_download`] and downloaded one at a time.
+    /// A post that fails does not stop the others.
+    /// Nsfw posts that are refused by [`ClientBuilder::allow_nsfw`](crate::ClientBuilder::allow_nsfw)
+    /// are reported as failed, after their metadata is fetched but before any file is downloaded.
+    ///
+    /// If `progress_path` is given, the job's [`ArchiveProgress`] is loaded from it on start,
+    /// and each post whose files all downloaded is appended to it.
+    /// Posts completed by an earlier run are skipped entirely, without any requests,
+    /// so an interrupted job can be resumed by running it again with the same path.
+    /// Posts that change after they are completed are not downloaded again,
+    /// and the recorded hashes are not checked,
+    /// so files that are changed or deleted after they are completed are not downloaded again either.
+    /// Only failing to record progress stops the job.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn archive_posts<I, S, P>(
+        &self,
+        ids: I,
+        out_dir: P,
+        progress_path: Option<&Path>,
+    ) -> Result<ArchiveSummary, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        let out_dir = out_dir.as_ref();
+
+        let progress = match progress_path {
+            Some(progress_path) => ArchiveProgress::load(progress_path).await?,
+            None => ArchiveProgress::default(),
+        };
+        let mut completed: HashSet<Box<str>> =
+            progress.posts.into_iter().map(|post| post.id).collect();
+
+        let mut summary = ArchiveSummary::default();
+        let mut seen = HashSet::new();
+        for id in ids {
+            let id = id.as_ref();
+            if !seen.insert(id.to_string()) {
+                continue;
+            }
+            if completed.contains(id) {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let (post_summary, archived) = match self.archive_post(id, out_dir).await {
+                Ok(result) => result,
+                Err(error) => {
+                    summary.failed.push((id.into(), error));
+                    continue;
+                }
+            };
+
+            if let (Some(progress_path), Some(archived)) = (progress_path, archived) {
+                ArchiveProgress::append(progress_path, &archived).await?;
+                completed.insert(archived.id);
+            }
+
+            summary.downloaded.push(post_summary);
+        }
+
+        Ok(summary)
+    }
+
+    /// Download a post into a directory named after its id inside `out_dir`.
+    ///
+    /// Returns the record of the post if all of its files downloaded.
+    async fn archive_post(
+        &self,
+        id: &str,
+        out_dir: &Path,
+    ) -> Result<(DownloadSummary, Option<ArchivedPost>), Error> {
+        let (post, source) = self.fetch_post_for_download(id).await?;
+        self.check_nsfw_allowed(&post)?;
+
+        let post_dir = out_dir.join(&*post.id);
+        let mut post_summary = self
+            .download_post_filtered(&post, &post_dir, |_file| true)
+            .await?;
+        post_summary.source = Some(source);
+
+        if !post_summary.failed.is_empty() {
+            return Ok((post_summary, None));
+        }
+
+        let mut files = Vec::with_capacity(post.images.len());
+        let files_on_disk = post
+            .images
+            .iter()
+            .filter(|file| file.kind() != FileKind::Other);
+        for file in files_on_disk {
+            let path = Self::download_path(&post_dir, file);
+            let mut reader = tokio::fs::File::open(&path).await?;
+            let mut writer = HashWriter::new();
+            tokio::io::copy(&mut reader, &mut writer).await?;
+
+            files.push(ArchivedFile {
+                id: file.id.clone(),
+                file_name: path
+                    .file_name()
+                    .map(|file_name| file_name.to_string_lossy().into())
+                    .unwrap_or_default(),
+                sha256: writer.finish(),
+            });
+        }
+
+        let archived = ArchivedPost {
+            id: id.into(),
+            files,
+        };
+
+        Ok((post_summary, Some(archived)))
+    }
+}
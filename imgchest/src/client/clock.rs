@@ -0,0 +1,31 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A source of time for the rate limiter.
+///
+/// The default is [`TokioClock`].
+/// Tests can use a clock that sleeps by advancing its own time,
+/// to check rate limiting without waiting.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+
+    /// Sleep for the given duration.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// The real clock, which sleeps with tokio.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
@@ -0,0 +1,405 @@
+use super::Client;
+use super::Endpoint;
+use crate::model::CDN_HOST;
+use crate::Error;
+use crate::FileKind;
+use crate::PostRef;
+use crate::ScrapedPost;
+use crate::ScrapedPostFile;
+use std::borrow::Cow;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinSet;
+
+/// A file that was downloaded.
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    /// The number of bytes downloaded.
+    pub size: u64,
+
+    /// The url the file was fetched from, after following redirects.
+    ///
+    /// This is only present if it differs from the requested link.
+    pub resolved_url: Option<String>,
+}
+
+/// Where the metadata of a downloaded post came from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PostSource {
+    /// The post was fetched from the api.
+    Api,
+
+    /// The post was scraped.
+    Scraped,
+}
+
+/// The result of downloading the files of a post.
+#[derive(Debug, Default)]
+pub struct DownloadSummary {
+    /// The id of the post.
+    pub post_id: Box<str>,
+
+    /// Where the post came from.
+    ///
+    /// This is `None` if the post was provided by the caller.
+    pub source: Option<PostSource>,
+
+    /// The number of files that were downloaded.
+    pub downloaded: usize,
+
+    /// The number of files that were skipped because they already existed.
+    pub skipped: usize,
+
+    /// The number of files that were not selected for download.
+    pub filtered: usize,
+
+    /// The number of files that were skipped because they are not files on the cdn.
+    ///
+    /// See [`FileKind::Other`].
+    pub unsupported: usize,
+
+    /// The files that failed to download, with their errors.
+    pub failed: Vec<(ScrapedPostFile, Error)>,
+}
+
+impl Client {
+    /// Fetch a post from a url or id, then download all of its files into a directory.
+    ///
+    /// If a token is set, the post is fetched from the api,
+    /// falling back to scraping if that fails.
+    /// Otherwise, it is scraped.
+    /// See [`Client::download_post_filtered`] for how files are downloaded.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn fetch_and_download<P>(
+        &self,
+        url_or_id: &str,
+        out_dir: P,
+    ) -> Result<DownloadSummary, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let post_ref: PostRef = url_or_id.parse()?;
+        let (post, source) = self.fetch_post_for_download(post_ref.post_id()?).await?;
+
+        let mut summary = self
+            .download_post_filtered(&post, out_dir, |_file| true)
+            .await?;
+        summary.source = Some(source);
+
+        Ok(summary)
+    }
+
+    /// Fetch a post to download.
+    ///
+    /// If a token is set, the post is fetched from the api,
+    /// falling back to scraping if that fails.
+    /// Otherwise, it is scraped.
+    pub(super) async fn fetch_post_for_download(
+        &self,
+        id: &str,
+    ) -> Result<(ScrapedPost, PostSource), Error> {
+        if self.get_token().is_some() {
+            match self.get_post(id).await {
+                Ok(post) => return Ok((post.into(), PostSource::Api)),
+                Err(error) => {
+                    tracing::warn!(
+                        id,
+                        "failed to get post from the api, scraping instead: {error}"
+                    );
+                }
+            }
+        }
+
+        Ok((self.get_scraped_post(id).await?, PostSource::Scraped))
+    }
+
+    /// Fail if a post is nsfw and the client does not allow downloading nsfw posts.
+    pub(super) fn check_nsfw_allowed(&self, post: &ScrapedPost) -> Result<(), Error> {
+        if post.nsfw && !self.state.allow_nsfw {
+            return Err(Error::NsfwPostRefused {
+                id: post.id.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the path that [`Client::download_post_filtered`] downloads a file of a post to.
+    ///
+    /// This is the file's name in `out_dir`, or its id if the link has no file name.
+    pub fn download_path(out_dir: &Path, file: &ScrapedPostFile) -> PathBuf {
+        out_dir.join(file.file_name().unwrap_or(&file.id))
+    }
+
+    /// Download the files of a post that match a predicate into a directory.
+    ///
+    /// Files are named after the last segment of their link.
+    /// Files that already exist in the directory are skipped.
+    /// Files are downloaded concurrently,
+    /// and a failed download does not stop the others.
+    /// A post with no files is not an error, and returns a summary with every count at zero.
+    /// Files that are not on the cdn, like embeds, are skipped with a warning,
+    /// as they may not be downloadable and may not have a file name.
+    ///
+    /// If [`ClientBuilder::allow_nsfw`](crate::ClientBuilder::allow_nsfw) is disabled,
+    /// this fails with [`Error::NsfwPostRefused`] for nsfw posts without downloading anything.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn download_post_filtered<P, F>(
+        &self,
+        post: &ScrapedPost,
+        out_dir: P,
+        predicate: F,
+    ) -> Result<DownloadSummary, Error>
+    where
+        P: AsRef<Path>,
+        F: Fn(&ScrapedPostFile) -> bool,
+    {
+        self.check_nsfw_allowed(post)?;
+
+        let out_dir = out_dir.as_ref();
+        tokio::fs::create_dir_all(out_dir).await?;
+
+        let mut summary = DownloadSummary {
+            post_id: post.id.clone(),
+            ..DownloadSummary::default()
+        };
+        let mut join_set = JoinSet::new();
+        for file in post.images.iter() {
+            if !predicate(file) {
+                summary.filtered += 1;
+                continue;
+            }
+            if file.kind() == FileKind::Other {
+                tracing::warn!(
+                    id = &*file.id,
+                    link = &*file.link,
+                    "skipping file that is not on the cdn"
+                );
+                summary.unsupported += 1;
+                continue;
+            }
+
+            let client = self.clone();
+            let file = file.clone();
+            let out_dir = out_dir.to_path_buf();
+            join_set.spawn(async move {
+                let result = async {
+                    let out_path = Self::download_path(&out_dir, &file);
+                    if tokio::fs::try_exists(&out_path).await? {
+                        return Ok(false);
+                    }
+
+                    client.download_file_to_path(&file.link, &out_path).await?;
+
+                    Ok(true)
+                }
+                .await;
+
+                (file, result)
+            });
+        }
+
+        while let Some(result) = join_set.join_next().await {
+            let (file, result) = result?;
+            match result {
+                Ok(true) => summary.downloaded += 1,
+                Ok(false) => summary.skipped += 1,
+                Err(error) => summary.failed.push((file, error)),
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Download a user's avatar to the given path.
+    ///
+    /// If a token is set, the avatar url is taken from [`Client::get_user`].
+    /// Otherwise, or if the API does not include it, it is scraped from the user's profile.
+    /// If neither has an avatar, [`Error::NoAvatar`] is returned.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn download_user_avatar<P>(
+        &self,
+        username: &str,
+        out_path: P,
+    ) -> Result<DownloadedFile, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut avatar = None;
+        if self.get_token().is_some() {
+            match self.get_user(username).await {
+                Ok(user) => avatar = user.avatar,
+                Err(error) => {
+                    tracing::warn!(
+                        username,
+                        "failed to get user from the api, scraping instead: {error}"
+                    );
+                }
+            }
+        }
+        if avatar.is_none() {
+            avatar = self.get_scraped_user(username).await?.avatar;
+        }
+
+        let avatar = avatar.ok_or_else(|| Error::NoAvatar {
+            username: username.into(),
+        })?;
+        self.download_file_to_path(&avatar, out_path).await
+    }
+
+    /// Download a file to the given path.
+    ///
+    /// The file is first downloaded next to the given path with a `.part` extension,
+    /// then renamed once it is complete.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn download_file_to_path<P>(
+        &self,
+        link: &str,
+        path: P,
+    ) -> Result<DownloadedFile, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".part");
+        let temp_path = PathBuf::from(temp_path);
+
+        let link = self.rewrite_cdn_link(link);
+        let mut response = self
+            .send(Endpoint::Download, self.client.get(&*link))
+            .await?
+            .error_for_status()?;
+        let resolved_url = Some(response.url().as_str())
+            .filter(|url| *url != link)
+            .map(String::from);
+
+        let result = async {
+            let mut file = tokio::fs::File::create(&temp_path).await?;
+            let size = self.write_response(&mut response, &mut file).await?;
+            file.sync_all().await?;
+
+            tokio::fs::rename(&temp_path, path).await?;
+
+            Ok(size)
+        }
+        .await;
+
+        let size = match result {
+            Ok(size) => size,
+            Err(error) => {
+                // Don't leave partial downloads around.
+                tokio::fs::remove_file(&temp_path).await.ok();
+                return Err(error);
+            }
+        };
+
+        Ok(DownloadedFile { size, resolved_url })
+    }
+
+    /// Download a file into the given writer.
+    ///
+    /// Returns the number of bytes written.
+    /// Errors while writing are returned as [`Error::Io`],
+    /// while network errors are returned as [`Error::Reqwest`].
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn download_file_to_writer<W>(&self, link: &str, mut writer: W) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let link = self.rewrite_cdn_link(link);
+        let mut response = self
+            .send(Endpoint::Download, self.client.get(&*link))
+            .await?
+            .error_for_status()?;
+        self.write_response(&mut response, &mut writer).await
+    }
+
+    /// Rewrite a cdn link to use the configured cdn base, if any.
+    fn rewrite_cdn_link<'a>(&self, link: &'a str) -> Cow<'a, str> {
+        let cdn_base = match self.state.cdn_base.as_ref() {
+            Some(cdn_base) => cdn_base,
+            None => return Cow::Borrowed(link),
+        };
+        let mut url = match reqwest::Url::parse(link) {
+            Ok(url) if url.host_str() == Some(CDN_HOST) => url,
+            _ => return Cow::Borrowed(link),
+        };
+
+        // These only fail for urls that cannot have a host, which cdn links always have.
+        url.set_scheme(cdn_base.scheme()).ok();
+        url.set_host(cdn_base.host_str()).ok();
+        url.set_port(cdn_base.port()).ok();
+
+        Cow::Owned(url.into())
+    }
+
+    /// Stream a response body into a writer, applying the download throttle.
+    async fn write_response<W>(
+        &self,
+        response: &mut reqwest::Response,
+        writer: &mut W,
+    ) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            if let Some(throttle) = self.state.download_throttle.as_ref() {
+                throttle.consume(chunk.len()).await;
+            }
+            writer.write_all(&chunk).await?;
+            size += u64::try_from(chunk.len()).unwrap();
+        }
+        writer.flush().await?;
+
+        Ok(size)
+    }
+}
+
+/// A bandwidth limiter shared by all downloads of a client.
+#[derive(Debug)]
+pub(super) struct Throttle {
+    bytes_per_second: NonZeroU64,
+
+    /// The time at which all bytes consumed so far will have been "paid" for.
+    next_free: std::sync::Mutex<Instant>,
+}
+
+impl Throttle {
+    pub(super) fn new(bytes_per_second: NonZeroU64) -> Self {
+        Self {
+            bytes_per_second,
+            next_free: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Account for the given number of bytes, sleeping if over the limit.
+    async fn consume(&self, bytes: usize) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second.get() as f64);
+
+        let sleep_duration = {
+            let mut next_free = self.next_free.lock().expect("throttle mutex poisoned");
+            let now = Instant::now();
+            *next_free = (*next_free).max(now) + cost;
+            next_free.saturating_duration_since(now)
+        };
+
+        tokio::time::sleep(sleep_duration).await;
+    }
+}
@@ -0,0 +1,10 @@
+/// A hook that runs on every request the client sends.
+///
+/// Interceptors run in the order they were added, after the request is built,
+/// so they see its final url and headers.
+/// They can add headers for things like correlation ids or custom auth, or record metrics.
+/// This includes api, scrape, and download requests.
+pub trait RequestInterceptor: std::fmt::Debug + Send + Sync {
+    /// Inspect or modify a request before it is sent.
+    fn intercept(&self, request: &mut reqwest::Request);
+}
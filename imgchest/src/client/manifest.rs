@@ -0,0 +1,130 @@
+use super::Client;
+use crate::Error;
+use crate::FileKind;
+use crate::ScrapedPost;
+use sha2::Digest;
+use sha2::Sha256;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use tokio::io::AsyncWrite;
+
+/// A manifest of a post's files, with content hashes.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PostManifest {
+    /// The post id
+    pub id: Box<str>,
+
+    /// The files of the post
+    pub files: Vec<ManifestFile>,
+}
+
+/// A file in a [`PostManifest`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ManifestFile {
+    /// The file id
+    pub file_id: Box<str>,
+
+    /// The position of the file in the post.
+    pub position: u32,
+
+    /// The link to the file
+    pub link: Box<str>,
+
+    /// The size of the file, in bytes
+    pub size: u64,
+
+    /// The sha256 hash of the file, as lowercase hex
+    pub sha256: String,
+}
+
+impl Client {
+    /// Build a manifest of a post's files.
+    ///
+    /// Each file is downloaded one at a time and hashed as it streams in,
+    /// without being stored.
+    /// Files that are not on the cdn, like embeds, are skipped with a warning,
+    /// so they are not in the manifest.
+    /// See [`FileKind::Other`].
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn build_post_manifest(&self, id: &str) -> Result<PostManifest, Error> {
+        let post = self.get_scraped_post(id).await?;
+        self.build_scraped_post_manifest(post).await
+    }
+
+    /// Build a manifest of the files of a post that was already scraped.
+    pub(crate) async fn build_scraped_post_manifest(
+        &self,
+        post: ScrapedPost,
+    ) -> Result<PostManifest, Error> {
+        let mut files = Vec::with_capacity(post.images.len());
+        for file in post.images.iter() {
+            if file.kind() == FileKind::Other {
+                tracing::warn!(
+                    id = &*file.id,
+                    link = &*file.link,
+                    "skipping file that is not on the cdn"
+                );
+                continue;
+            }
+
+            let mut writer = HashWriter::new();
+            let size = self
+                .download_file_to_writer(&file.link, &mut writer)
+                .await?;
+
+            files.push(ManifestFile {
+                file_id: file.id.clone(),
+                position: file.position,
+                link: file.link.clone(),
+                size,
+                sha256: writer.finish(),
+            });
+        }
+
+        Ok(PostManifest { id: post.id, files })
+    }
+}
+
+/// A writer that hashes its input.
+pub(super) struct HashWriter {
+    hasher: Sha256,
+}
+
+impl HashWriter {
+    pub(super) fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Get the hash as lowercase hex.
+    pub(super) fn finish(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+impl AsyncWrite for HashWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.hasher.update(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
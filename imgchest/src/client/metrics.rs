@@ -0,0 +1,150 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+/// The category of a request, for metrics.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Endpoint {
+    Post,
+    File,
+    User,
+    Scrape,
+    Download,
+    Other,
+}
+
+/// A snapshot of a client's request counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientMetrics {
+    /// The total number of requests sent
+    pub requests: u64,
+
+    /// The number of post api requests
+    pub post_requests: u64,
+
+    /// The number of file api requests
+    pub file_requests: u64,
+
+    /// The number of user api requests
+    pub user_requests: u64,
+
+    /// The number of scraped pages requested
+    pub scrape_requests: u64,
+
+    /// The number of file downloads
+    pub download_requests: u64,
+
+    /// The number of other requests, like health checks
+    pub other_requests: u64,
+
+    /// The number of responses with a 2xx status
+    pub success_responses: u64,
+
+    /// The number of responses with a 3xx status
+    pub redirect_responses: u64,
+
+    /// The number of responses with a 4xx status
+    pub client_error_responses: u64,
+
+    /// The number of responses with a 5xx status
+    pub server_error_responses: u64,
+
+    /// The number of requests that failed without a response
+    pub failed_requests: u64,
+}
+
+/// Request counters.
+#[derive(Debug, Default)]
+pub(super) struct Metrics {
+    requests: AtomicU64,
+    post_requests: AtomicU64,
+    file_requests: AtomicU64,
+    user_requests: AtomicU64,
+    scrape_requests: AtomicU64,
+    download_requests: AtomicU64,
+    other_requests: AtomicU64,
+    success_responses: AtomicU64,
+    redirect_responses: AtomicU64,
+    client_error_responses: AtomicU64,
+    server_error_responses: AtomicU64,
+    failed_requests: AtomicU64,
+}
+
+impl Metrics {
+    /// Record the result of a request.
+    pub(super) fn record(
+        &self,
+        endpoint: Endpoint,
+        result: &Result<reqwest::Response, reqwest::Error>,
+    ) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let endpoint = match endpoint {
+            Endpoint::Post => &self.post_requests,
+            Endpoint::File => &self.file_requests,
+            Endpoint::User => &self.user_requests,
+            Endpoint::Scrape => &self.scrape_requests,
+            Endpoint::Download => &self.download_requests,
+            Endpoint::Other => &self.other_requests,
+        };
+        endpoint.fetch_add(1, Ordering::Relaxed);
+
+        let status = match result {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_success() {
+                    Some(&self.success_responses)
+                } else if status.is_redirection() {
+                    Some(&self.redirect_responses)
+                } else if status.is_client_error() {
+                    Some(&self.client_error_responses)
+                } else if status.is_server_error() {
+                    Some(&self.server_error_responses)
+                } else {
+                    None
+                }
+            }
+            Err(_error) => Some(&self.failed_requests),
+        };
+        if let Some(status) = status {
+            status.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Take a snapshot of the counters.
+    pub(super) fn snapshot(&self) -> ClientMetrics {
+        ClientMetrics {
+            requests: self.requests.load(Ordering::Relaxed),
+            post_requests: self.post_requests.load(Ordering::Relaxed),
+            file_requests: self.file_requests.load(Ordering::Relaxed),
+            user_requests: self.user_requests.load(Ordering::Relaxed),
+            scrape_requests: self.scrape_requests.load(Ordering::Relaxed),
+            download_requests: self.download_requests.load(Ordering::Relaxed),
+            other_requests: self.other_requests.load(Ordering::Relaxed),
+            success_responses: self.success_responses.load(Ordering::Relaxed),
+            redirect_responses: self.redirect_responses.load(Ordering::Relaxed),
+            client_error_responses: self.client_error_responses.load(Ordering::Relaxed),
+            server_error_responses: self.server_error_responses.load(Ordering::Relaxed),
+            failed_requests: self.failed_requests.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reset all counters to 0.
+    pub(super) fn reset(&self) {
+        for counter in [
+            &self.requests,
+            &self.post_requests,
+            &self.file_requests,
+            &self.user_requests,
+            &self.scrape_requests,
+            &self.download_requests,
+            &self.other_requests,
+            &self.success_responses,
+            &self.redirect_responses,
+            &self.client_error_responses,
+            &self.server_error_responses,
+            &self.failed_requests,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
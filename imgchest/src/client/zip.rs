@@ -0,0 +1,72 @@
+use super::Client;
+use crate::Error;
+use crate::FileKind;
+use crate::ScrapedPost;
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::Compression;
+use async_zip::ZipEntryBuilder;
+use tokio::io::AsyncWrite;
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+
+impl Client {
+    /// Download a post into a zip archive written to the given writer.
+    ///
+    /// The archive has a `post.json` entry with the post's versioned json,
+    /// followed by an entry for each file named `{position}-{file name}`.
+    /// Files that are not on the cdn, like embeds, are skipped with a warning.
+    /// Positions are zero-padded so the entries sort in post order.
+    ///
+    /// Files are downloaded one at a time and streamed into the archive without being buffered.
+    /// They are stored without compression, as images and videos are already compressed.
+    /// The first failed download fails the whole archive.
+    /// Nsfw posts fail with [`Error::NsfwPostRefused`] before anything is written
+    /// if [`ClientBuilder::allow_nsfw`](crate::ClientBuilder::allow_nsfw) is disabled.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    pub async fn download_post_to_zip<W>(&self, post: &ScrapedPost, writer: W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.check_nsfw_allowed(post)?;
+
+        let mut zip = ZipFileWriter::with_tokio(writer);
+
+        let post_json = post.to_versioned_json().to_string();
+        let entry = ZipEntryBuilder::new("post.json".into(), Compression::Stored);
+        zip.write_entry_whole(entry, post_json.as_bytes()).await?;
+
+        let width = post
+            .images
+            .iter()
+            .map(|file| file.position)
+            .max()
+            .unwrap_or(0)
+            .to_string()
+            .len();
+        for file in post.images.iter() {
+            if file.kind() == FileKind::Other {
+                tracing::warn!(
+                    id = &*file.id,
+                    link = &*file.link,
+                    "skipping file that is not on the cdn"
+                );
+                continue;
+            }
+
+            let file_name = file.file_name().unwrap_or(&file.id);
+            let entry_name = format!("{:0width$}-{file_name}", file.position);
+            let entry = ZipEntryBuilder::new(entry_name.into(), Compression::Stored);
+
+            let mut entry_writer = zip.write_entry_stream(entry).await?.compat_write();
+            self.download_file_to_writer(&file.link, &mut entry_writer)
+                .await?;
+            entry_writer.into_inner().close().await?;
+        }
+
+        let mut writer = zip.close().await?.into_inner();
+        tokio::io::AsyncWriteExt::flush(&mut writer).await?;
+
+        Ok(())
+    }
+}
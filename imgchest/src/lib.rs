@@ -1,30 +1,70 @@
 mod client;
 mod model;
+mod post_ref;
 
 pub use self::client::Client;
+pub use crate::client::ArchiveProgress;
+pub use crate::client::ArchiveSummary;
+pub use crate::client::ArchivedFile;
+pub use crate::client::ArchivedPost;
+pub use crate::client::CachedPost;
+pub use crate::client::ClientBuilder;
+pub use crate::client::ClientMetrics;
+pub use crate::client::Clock;
 pub use crate::client::CreatePostBuilder;
+pub use crate::client::DownloadSummary;
+pub use crate::client::DownloadedFile;
+pub use crate::client::FullPost;
+pub use crate::client::ManifestFile;
+pub use crate::client::PostManifest;
+pub use crate::client::PostSource;
+pub use crate::client::RequestInterceptor;
+pub use crate::client::RequestPriority;
+pub use crate::client::TitleOutcome;
+pub use crate::client::TokioClock;
 pub use crate::client::UpdatePostBuilder;
+pub use crate::client::UpdatePostField;
 pub use crate::client::UploadPostFile;
+pub use crate::client::MAX_IMAGES_PER_REQUEST;
+pub use crate::client::SUPPORTED_UPLOAD_EXTENSIONS;
+use crate::model::parse_post_views;
 use crate::model::ApiCompletedResponse;
+use crate::model::ApiPostCover;
 use crate::model::ApiResponse;
 use crate::model::ApiUpdateFilesBulkRequest;
+pub use crate::model::FileKind;
 pub use crate::model::FileUpdate;
 pub use crate::model::InvalidScrapedPostError;
 pub use crate::model::Post;
 pub use crate::model::PostFile;
+pub use crate::model::PostMeta;
 pub use crate::model::PostPrivacy;
 pub use crate::model::ScrapedPost;
 pub use crate::model::ScrapedPostFile;
+pub use crate::model::ScrapedUser;
+use crate::model::ScrapedUserPostsPage;
 pub use crate::model::User;
+pub use crate::post_ref::InvalidPostRefError;
+pub use crate::post_ref::PostRef;
 pub use reqwest::Body;
 
 /// The error
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Reqwest error
     #[error("reqwest http error")]
     Reqwest(#[from] reqwest::Error),
 
+    /// An io error, like failing to write a download
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+
+    /// Failed to write a zip archive
+    #[cfg(feature = "zip")]
+    #[error("failed to write zip archive")]
+    Zip(#[from] async_zip::error::ZipError),
+
     /// Failed to join tokio task
     #[error("failed to join tokio task")]
     TokioJoin(#[from] tokio::task::JoinError),
@@ -33,6 +73,10 @@ pub enum Error {
     #[error("invalid scraped post")]
     InvalidScrapedPost(#[from] InvalidScrapedPostError),
 
+    /// Failed to parse a user profile
+    #[error("invalid scraped user")]
+    InvalidScrapedUser(#[source] InvalidScrapedPostError),
+
     /// Missing a token
     #[error("missing token")]
     MissingToken,
@@ -41,6 +85,57 @@ pub enum Error {
     #[error("need at least 1 image")]
     MissingImages,
 
+    /// The post privacy was not set, but the client requires it
+    #[error("post privacy was not specified")]
+    PrivacyNotSpecified,
+
+    /// A user has no known avatar
+    #[error("no avatar was found for user \"{username}\"")]
+    NoAvatar {
+        /// The user name
+        username: Box<str>,
+    },
+
+    /// A post has no files to use as its cover
+    #[error("post \"{id}\" has no images")]
+    NoCover {
+        /// The post id
+        id: Box<str>,
+    },
+
+    /// A post is nsfw, and the client does not allow downloading nsfw posts
+    #[error("post \"{id}\" is nsfw, which is not allowed")]
+    NsfwPostRefused {
+        /// The post id
+        id: Box<str>,
+    },
+
+    /// A post was recently not found, so it was not requested again
+    #[error("post \"{id}\" was recently not found")]
+    PostKnownMissing {
+        /// The post id
+        id: Box<str>,
+    },
+
+    /// A bulk update had no updates
+    #[error("need at least 1 update")]
+    NoUpdates,
+
+    /// A successful api response did not have the expected shape
+    #[error("api response had an unexpected shape")]
+    UnexpectedResponseShape {
+        /// The raw response body
+        body: String,
+
+        /// The error from parsing the body
+        #[source]
+        error: serde_json::Error,
+    },
+
+    /// An api response had null or missing data
+    #[error("api response had no data")]
+    EmptyResponse,
+
     /// An api operation was not successful
     #[error("api operation was not successful")]
     ApiOperationFailed,
@@ -57,17 +152,138 @@ pub enum Error {
     },
 
     /// Missing description
-    #[error("missing description")]
+    ///
+    /// The API does not allow empty descriptions.
+    #[error("missing description, the API does not allow empty descriptions")]
     MissingDescription,
 
+    /// An upload request had more images than the configured maximum
+    #[error("{provided} images were provided, over the maximum of {limit} per request")]
+    TooManyImages {
+        /// The maximum number of images per request
+        limit: usize,
+
+        /// The number of images provided
+        provided: usize,
+    },
+
+    /// Descriptions were given for images added to an existing post, which the API does not support
+    #[error("descriptions can only be set when creating a post, set them after adding the images instead")]
+    DescriptionsNotSupported,
+
+    /// A description was keyed by a file name that matched no image
+    #[error("no image has the file name \"{file_name}\" for its description")]
+    UnmatchedDescription {
+        /// The file name
+        file_name: String,
+    },
+
+    /// A description was longer than the configured maximum
+    #[error("description has {actual} chars, over the maximum of {max}")]
+    DescriptionTooLong {
+        /// The maximum number of chars
+        max: usize,
+
+        /// The number of chars in the description
+        actual: usize,
+    },
+
     /// The title is too short.
     #[error("title too short, must be at least 3 characters")]
     TitleTooShort,
+
+    /// A file has an extension that is not accepted for uploads
+    #[error("unsupported upload format for \"{file_name}\"")]
+    UnsupportedFormat {
+        /// The file name
+        file_name: Box<str>,
+    },
+
+    /// A delete url was invalid or did not point at the API
+    #[error("invalid delete url")]
+    InvalidDeleteUrl,
+
+    /// The cdn base url was invalid
+    #[error("invalid cdn base url")]
+    InvalidCdnBase,
+
+    /// The server rejected an upload for being too large
+    #[error("upload exceeds the server's size limit")]
+    PayloadTooLarge,
+
+    /// A response body was larger than the allowed limit
+    #[error("response body exceeds limit of {limit} bytes")]
+    ResponseTooLarge {
+        /// The limit, in bytes
+        limit: u64,
+    },
+
+    /// A response had an unexpected content type
+    #[error("unexpected content type {content_type:?} with status {status}")]
+    UnexpectedContentType {
+        /// The content type, if present
+        content_type: Option<Box<str>>,
+
+        /// The response status
+        status: reqwest::StatusCode,
+    },
+
+    /// Adding files to a post would exceed the configured file limit
+    #[error("adding files would give the post {would_be} files, over the limit of {limit}")]
+    PostFileLimitExceeded {
+        /// The configured limit
+        limit: u64,
+
+        /// The number of files the post would have
+        would_be: u64,
+    },
+
+    /// A post has no file at a position
+    #[error("the post has no file at position {position}")]
+    InvalidPosition {
+        /// The position
+        position: std::num::NonZeroU32,
+    },
+
+    /// A post reference was invalid
+    #[error(transparent)]
+    InvalidPostRef(#[from] InvalidPostRefError),
+
+    /// A scraped page redirected, and following scrape redirects is disabled
+    #[error("page redirected to \"{to}\" with status {status}")]
+    PageRedirected {
+        /// The redirect target, or an empty string if it was missing
+        to: String,
+
+        /// The response status
+        status: reqwest::StatusCode,
+    },
+}
+
+impl Error {
+    /// Returns true if the failed operation may succeed if retried.
+    ///
+    /// This is true for connection errors, timeouts,
+    /// and responses with a 429 or 5xx status.
+    /// It is false for every other error,
+    /// including other 4xx statuses and client-side validation errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(error) => match error.status() {
+                Some(status) => {
+                    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                }
+                None => error.is_timeout() || error.is_connect() || error.is_request(),
+            },
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::num::NonZeroUsize;
     use std::sync::OnceLock;
     use time::format_description::well_known::Iso8601;
     use time::OffsetDateTime;
@@ -76,6 +292,43 @@ mod test {
     const GIF_POST_ID: &str = "pwl7lgepyx2";
     const VIDEO_POST_ID: &str = "ej7mko58jyd";
 
+    const POST_FIXTURE: &str = r#"{
+        "id": "3qe4gdvj4j2",
+        "title": "Donkey Kong - Video Game From The Mid 80's",
+        "username": "LunarLandr",
+        "privacy": "public",
+        "report_status": 1,
+        "views": 198,
+        "nsfw": 0,
+        "image_count": 2,
+        "created": "2019-11-03T00:36:00.000000Z",
+        "images": [
+            {
+                "id": "nw7w6cmlvye",
+                "description": "**Description**  \nReleased in the arcades in 1981, Donkey Kong",
+                "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                "position": 1,
+                "created": "2019-11-03T00:36:00.000000Z"
+            },
+            {
+                "id": "kwye3cpag4b",
+                "description": null,
+                "link": "https://cdn.imgchest.com/files/kwye3cpag4b.png",
+                "position": 2,
+                "created": "2019-11-03T00:36:00.000000Z",
+                "original_name": "kong.png"
+            }
+        ],
+        "delete_url": "https://api.imgchest.com/p/3qe4gdvj4j2/delete"
+    }"#;
+
+    const USER_FIXTURE: &str = r#"{
+        "name": "LunarLandr",
+        "posts": 10,
+        "comments": 2,
+        "created": "2019-09-25T01:00:45.000000Z"
+    }"#;
+
     fn get_token() -> &'static str {
         static TOKEN: OnceLock<String> = OnceLock::new();
         TOKEN.get_or_init(|| {
@@ -103,6 +356,1242 @@ mod test {
         })
     }
 
+    /// Get an empty temporary directory that no other test or test run uses.
+    ///
+    /// Anything left over from an earlier run is removed first.
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("imgchest-test-{}-{name}-{n}", std::process::id()));
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => panic!("failed to remove old test dir: {error}"),
+        }
+        dir
+    }
+
+    /// Serve one canned response on a local port.
+    ///
+    /// The returned task resolves to the request head once the client closes the connection.
+    async fn serve_once(
+        response: impl Into<Vec<u8>>,
+    ) -> (std::net::SocketAddr, tokio::task::JoinHandle<String>) {
+        use tokio::io::AsyncReadExt;
+        use tokio::io::AsyncWriteExt;
+
+        let response = response.into();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut buffer = [0; 1024];
+                let n = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream.write_all(&response).await.unwrap();
+
+            // Keep the connection open until the client is done with it.
+            let mut buffer = [0; 1024];
+            while stream.read(&mut buffer).await.is_ok_and(|n| n > 0) {}
+
+            String::from_utf8(request).unwrap()
+        });
+
+        (addr, server)
+    }
+
+    #[test]
+    fn client_debug_redacts_token() {
+        let client = Client::new();
+        client.set_token("secret-token");
+
+        client.add_token("other-secret-token");
+
+        let debug = format!("{client:?}");
+        assert!(!debug.contains("secret-token"));
+        assert!(debug.matches("value: <redacted>").count() == 2);
+    }
+
+    #[test]
+    fn post_round_trip() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture.clone()).expect("failed to parse post");
+
+        assert!(&*post.id == "3qe4gdvj4j2");
+        assert!(post.privacy == PostPrivacy::Public);
+        assert!(!post.nsfw);
+        assert!(
+            post.created
+                == OffsetDateTime::parse("2019-11-03T00:36:00.000000Z", &Iso8601::DEFAULT).unwrap()
+        );
+        assert!(post.images[0].original_name.is_none());
+        assert!(post.images[1].original_name.as_deref() == Some("kong.png"));
+
+        let value = serde_json::to_value(&post).expect("failed to serialize post");
+        assert!(value == fixture);
+    }
+
+    #[test]
+    fn post_display() {
+        let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        fixture["title"] = serde_json::Value::Null;
+        let post: Post = serde_json::from_value(fixture).unwrap();
+
+        let summary = post.to_string();
+        assert!(
+            summary
+                == format!(
+                    "{} by {} ({} images, {} views)",
+                    post.id, post.username, post.image_count, post.views
+                ),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn post_lenient_timestamps() {
+        let expected =
+            OffsetDateTime::parse("2019-11-03T00:36:00.000000Z", &Iso8601::DEFAULT).unwrap();
+
+        for created in [
+            "2019-11-03T00:36:00Z",
+            "2019-11-03T00:36:00.000Z",
+            "2019-11-03T02:36:00+02:00",
+            "2019-11-02T19:36:00.000000-05:00",
+            "2019-11-03 00:36:00",
+        ] {
+            let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+            fixture["created"] = created.into();
+
+            let post: Post = serde_json::from_value(fixture)
+                .unwrap_or_else(|error| panic!("failed to parse \"{created}\": {error}"));
+            assert!(post.created == expected, "{created}");
+            assert!(post.created.offset().is_utc(), "{created}");
+        }
+    }
+
+    #[test]
+    fn post_invalid_image_error() {
+        let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        fixture["images"][1]["created"] = serde_json::Value::Null;
+
+        let error = serde_json::from_value::<Post>(fixture).expect_err("post should be invalid");
+        let error = error.to_string();
+        assert!(error.contains("image 1 (id kwye3cpag4b)"), "{error}");
+    }
+
+    #[test]
+    fn post_missing_position() {
+        let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        fixture["images"][0]["position"] = 0.into();
+        fixture["images"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("position");
+
+        let post: Post = serde_json::from_value(fixture).expect("failed to parse post");
+        assert!(post.images[0].position.get() == 1);
+        assert!(post.images[1].position.get() == 2);
+    }
+
+    #[test]
+    fn post_meta_skips_images() {
+        let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture.clone()).expect("failed to parse post");
+
+        // The images are not deserialized, so invalid files are not an error.
+        fixture["images"] = serde_json::json!([{ "invalid": true }]);
+        let meta: PostMeta = serde_json::from_value(fixture).expect("failed to parse post meta");
+
+        assert!(meta.id == post.id);
+        assert!(meta.title == post.title);
+        assert!(meta.username == post.username);
+        assert!(meta.privacy == post.privacy);
+        assert!(meta.views == post.views);
+        assert!(meta.nsfw == post.nsfw);
+        assert!(meta.image_count == post.image_count);
+    }
+
+    #[test]
+    fn file_name_and_extension() {
+        let file = |link: &str| ScrapedPostFile {
+            id: "nw7w6cmlvye".into(),
+            description: None,
+            link: link.into(),
+            position: 1,
+            created: None,
+        };
+
+        let png = file("https://cdn.imgchest.com/files/nw7w6cmlvye.png?v=1");
+        assert!(png.file_name() == Some("nw7w6cmlvye.png"));
+        assert!(png.extension() == Some("png"));
+
+        let bare = file("https://cdn.imgchest.com/files/nw7w6cmlvye");
+        assert!(bare.file_name() == Some("nw7w6cmlvye"));
+        assert!(bare.extension().is_none());
+
+        let hidden = file("https://cdn.imgchest.com/files/.png");
+        assert!(hidden.extension().is_none());
+
+        let directory = file("https://cdn.imgchest.com/files/");
+        assert!(directory.file_name().is_none());
+        assert!(directory.extension().is_none());
+
+        let post: Post = serde_json::from_str(POST_FIXTURE).unwrap();
+        assert!(post.images[0].extension().is_some());
+        assert!(post.images[0].file_name() == post.images[0].link.rsplit('/').next());
+    }
+
+    #[test]
+    fn user_round_trip() {
+        let fixture: serde_json::Value = serde_json::from_str(USER_FIXTURE).unwrap();
+        let user: User = serde_json::from_value(fixture.clone()).expect("failed to parse user");
+
+        assert!(&*user.name == "LunarLandr");
+        assert!(user.posts == 10);
+
+        let value = serde_json::to_value(&user).expect("failed to serialize user");
+        assert!(value == fixture);
+    }
+
+    #[test]
+    fn user_recent_posts() {
+        let mut fixture: serde_json::Value = serde_json::from_str(USER_FIXTURE).unwrap();
+        let user: User = serde_json::from_value(fixture.clone()).expect("failed to parse user");
+        assert!(user.recent_posts.is_empty());
+
+        fixture["recent_posts"] =
+            serde_json::json!([serde_json::from_str::<serde_json::Value>(POST_FIXTURE).unwrap()]);
+        let user: User = serde_json::from_value(fixture).expect("failed to parse user");
+        assert!(user.recent_posts.len() == 1);
+        assert!(&*user.recent_posts[0].id == "3qe4gdvj4j2");
+    }
+
+    #[test]
+    fn user_string_counts() {
+        let mut fixture: serde_json::Value = serde_json::from_str(USER_FIXTURE).unwrap();
+        fixture["posts"] = "10".into();
+        fixture["comments"] = "2".into();
+
+        let user: User = serde_json::from_value(fixture).expect("failed to parse user");
+        assert!(user.posts == 10);
+        assert!(user.comments == 2);
+    }
+
+    #[test]
+    fn post_next_page_url() {
+        let url = "https://api.imgchest.com/v1/post/3qe4gdvj4j2";
+        let post: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+
+        let page = serde_json::json!({
+            "data": post,
+            "meta": { "current_page": 1, "last_page": 2 },
+        });
+        let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+        assert!(client::next_page_url(url, &page).as_deref() == Some(&*format!("{url}?page=2")));
+
+        let page = serde_json::json!({
+            "data": post,
+            "meta": { "current_page": 2, "last_page": 2 },
+        });
+        let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+        assert!(client::next_page_url(url, &page).is_none());
+
+        let page = serde_json::json!({
+            "data": post,
+            "links": { "next": "https://api.imgchest.com/next" },
+            "meta": { "current_page": 1, "last_page": 2 },
+        });
+        let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+        assert!(
+            client::next_page_url(url, &page).as_deref() == Some("https://api.imgchest.com/next")
+        );
+
+        let page = serde_json::json!({ "data": post });
+        let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+        assert!(client::next_page_url(url, &page).is_none());
+
+        // The token is sent with the next page, so other origins are not followed.
+        for next in [
+            "https://example.com/v1/post/3qe4gdvj4j2?page=2",
+            "http://api.imgchest.com/v1/post/3qe4gdvj4j2?page=2",
+            "https://api.imgchest.com:8443/v1/post/3qe4gdvj4j2?page=2",
+        ] {
+            let page = serde_json::json!({
+                "data": post,
+                "links": { "next": next },
+                "meta": { "current_page": 1, "last_page": 2 },
+            });
+            let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+            assert!(client::next_page_url(url, &page).is_none(), "{next}");
+        }
+
+        let page = serde_json::json!({
+            "data": post,
+            "meta": { "current_page": 1, "last_page": 3 },
+        });
+        let page: ApiResponse<Post> = serde_json::from_value(page).unwrap();
+        assert!(
+            client::next_page_url(&format!("{url}?sort=new&page=1"), &page).as_deref()
+                == Some(&*format!("{url}?sort=new&page=2"))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_remaining_post_pages() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let mut first_page = fixture.clone();
+        first_page["images"] = serde_json::json!([fixture["images"][0]]);
+        let mut second_page = fixture.clone();
+        second_page["images"] = serde_json::json!([fixture["images"][1]]);
+
+        let body = serde_json::json!({
+            "data": second_page,
+            "meta": { "current_page": 2, "last_page": 2 },
+        })
+        .to_string();
+        let (addr, server) = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))
+        .await;
+
+        let url = format!("http://{addr}/v1/post/3qe4gdvj4j2");
+        let response: ApiResponse<Post> = serde_json::from_value(serde_json::json!({
+            "data": first_page,
+            "meta": { "current_page": 1, "last_page": 2 },
+        }))
+        .unwrap();
+
+        let client = Client::new();
+        let token = client::TokenState::new(
+            "token",
+            std::sync::Arc::new(TokioClock),
+            client::RateLimit::default(),
+        );
+        let post = client
+            .get_remaining_post_pages(&token, RequestPriority::Normal, &url, response)
+            .await
+            .expect("failed to get remaining pages");
+
+        let ids: Vec<_> = post.images.iter().map(|file| &*file.id).collect();
+        assert!(ids == ["nw7w6cmlvye", "kwye3cpag4b"], "{ids:?}");
+
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("GET /v1/post/3qe4gdvj4j2?page=2 HTTP/1.1\r\n"),
+            "{request}"
+        );
+    }
+
+    #[test]
+    fn empty_response_data() {
+        let response: ApiResponse<Option<PostFile>> =
+            serde_json::from_str(r#"{"data":null}"#).expect("failed to parse null data");
+        assert!(response.data.is_none());
+
+        let response: ApiResponse<Option<PostFile>> =
+            serde_json::from_str("{}").expect("failed to parse missing data");
+        assert!(response.data.is_none());
+    }
+
+    #[test]
+    fn update_post_unapplied_fields() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture).unwrap();
+
+        let mut builder = UpdatePostBuilder::new();
+        builder
+            .title(post.title.as_deref().unwrap())
+            .privacy(post.privacy)
+            .nsfw(!post.nsfw);
+        assert!(builder.unapplied_fields(&post) == [UpdatePostField::Nsfw]);
+
+        assert!(UpdatePostBuilder::new().unapplied_fields(&post).is_empty());
+    }
+
+    #[test]
+    fn update_post_title_outcome() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture).unwrap();
+        let title = post.title.as_deref().unwrap();
+
+        let mut builder = UpdatePostBuilder::new();
+        assert!(builder.title_outcome(&post).is_none());
+
+        builder.title(title);
+        assert!(builder.title_outcome(&post) == Some(TitleOutcome::Applied));
+
+        builder.title(format!("  {title} "));
+        assert!(
+            builder.title_outcome(&post)
+                == Some(TitleOutcome::Trimmed {
+                    effective: title.into()
+                })
+        );
+        assert!(builder.unapplied_fields(&post).is_empty());
+
+        builder.title(format!("{title} (Remastered)"));
+        assert!(
+            builder.title_outcome(&post)
+                == Some(TitleOutcome::Truncated {
+                    effective: title.into()
+                })
+        );
+
+        assert!(builder.unapplied_fields(&post) == [UpdatePostField::Title]);
+
+        // A rename that extends the old title looks like truncation if it was ignored,
+        // so it is not counted as applied.
+        let mut renamed: Post = serde_json::from_str(POST_FIXTURE).unwrap();
+        renamed.title = Some("Comic".into());
+        let mut rename = UpdatePostBuilder::new();
+        rename.title("Comic Part 2");
+        assert!(
+            rename.title_outcome(&renamed)
+                == Some(TitleOutcome::Truncated {
+                    effective: "Comic".into()
+                })
+        );
+        assert!(rename.unapplied_fields(&renamed) == [UpdatePostField::Title]);
+
+        builder.title("Something Else");
+        assert!(
+            builder.title_outcome(&post)
+                == Some(TitleOutcome::NotApplied {
+                    effective: Some(title.into())
+                })
+        );
+        assert!(builder.unapplied_fields(&post) == [UpdatePostField::Title]);
+    }
+
+    #[test]
+    fn post_request_encodings() {
+        let client = Client::new();
+
+        // The server silently ignores updates that are not form-urlencoded.
+        let mut builder = UpdatePostBuilder::new();
+        builder.title("test title").nsfw(false);
+        let request = client
+            .update_post_request("token", "3qe4gdvj4j2", &builder)
+            .expect("failed to build update request")
+            .build()
+            .expect("failed to build update request");
+        let content_type = request.headers()[reqwest::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap();
+        assert!(
+            content_type == "application/x-www-form-urlencoded",
+            "{content_type}"
+        );
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .title("test title")
+            .image(UploadPostFile::from_static("1.png", b""));
+        let request = client
+            .create_post_request("token", builder)
+            .expect("failed to build create request")
+            .build()
+            .expect("failed to build create request");
+        let content_type = request.headers()[reqwest::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap();
+        assert!(
+            content_type.starts_with("multipart/form-data;"),
+            "{content_type}"
+        );
+    }
+
+    #[tokio::test]
+    async fn descriptions_in_later_batches() {
+        let client = Client::new();
+
+        // This is rejected before the post is created, so no token is needed.
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .image(UploadPostFile::from_static("1.png", b"").with_description("one"))
+            .image(UploadPostFile::from_static("2.png", b""))
+            .image(UploadPostFile::from_static("3.png", b"").with_description("three"));
+        let error = client
+            .create_large_post(builder, NonZeroUsize::new(2))
+            .await
+            .expect_err("the second batch has a description");
+        assert!(
+            matches!(error, Error::DescriptionsNotSupported),
+            "{error:?}"
+        );
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .image(UploadPostFile::from_static("1.png", b""))
+            .image(UploadPostFile::from_static("3.png", b""))
+            .descriptions_by_filename(std::collections::HashMap::from([(
+                "3.png".to_string(),
+                "three".to_string(),
+            )]));
+        let mut batches = 0;
+        let error = client
+            .create_large_post_with_progress(builder, NonZeroUsize::new(1), |_post, _uploaded| {
+                batches += 1
+            })
+            .await
+            .expect_err("the second batch has a description");
+        assert!(batches == 0);
+        assert!(
+            matches!(error, Error::DescriptionsNotSupported),
+            "{error:?}"
+        );
+
+        client.set_token("token");
+        let error = client
+            .add_post_images(
+                "3qe4gdvj4j2",
+                [UploadPostFile::from_static("4.png", b"").with_description("four")],
+            )
+            .await
+            .expect_err("descriptions cannot be added");
+        assert!(
+            matches!(error, Error::DescriptionsNotSupported),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn descriptions_by_filename() {
+        let descriptions = std::collections::HashMap::from([
+            ("2.png".to_string(), "two".to_string()),
+            ("3.png".to_string(), "three".to_string()),
+        ]);
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .image(UploadPostFile::from_static("1.png", b"").with_description("one"))
+            .image(UploadPostFile::from_static("2.png", b""))
+            .descriptions_by_filename(descriptions.clone());
+        builder
+            .apply_descriptions_by_filename()
+            .expect("unmatched descriptions should be ignored");
+        assert!(builder.images[0].description() == Some("one"));
+        assert!(builder.images[1].description() == Some("two"));
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .image(UploadPostFile::from_static("2.png", b""))
+            .descriptions_by_filename(descriptions)
+            .deny_unmatched_descriptions(true);
+        let error = builder
+            .apply_descriptions_by_filename()
+            .expect_err("unmatched descriptions should be denied");
+        assert!(matches!(
+            error,
+            Error::UnmatchedDescription { file_name } if file_name == "3.png"
+        ));
+    }
+
+    #[test]
+    fn require_explicit_privacy() {
+        let client = Client::builder()
+            .require_explicit_privacy(true)
+            .build()
+            .expect("failed to build client");
+
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_static("1.png", b""));
+        let error = client
+            .create_post_request("token", builder)
+            .expect_err("privacy should be required");
+        assert!(matches!(error, Error::PrivacyNotSpecified));
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .privacy(PostPrivacy::Hidden)
+            .image(UploadPostFile::from_static("1.png", b""));
+        let _request = client
+            .create_post_request("token", builder)
+            .expect("privacy should be specified");
+    }
+
+    #[test]
+    fn sanitize_file_name() {
+        assert!(client::sanitize_file_name("1.png") == "1.png");
+        assert!(client::sanitize_file_name("/home/user/my photo?.png") == "my_photo_.png");
+        assert!(client::sanitize_file_name("C:\\Users\\user\\a<b>.JPG") == "a_b_.JPG");
+        assert!(client::sanitize_file_name(".hidden.gif") == "hidden.gif");
+        assert!(client::sanitize_file_name(".png") == "file.png");
+        assert!(client::sanitize_file_name("") == "file");
+
+        let file = UploadPostFile::from_static("/tmp/1.png", b"").with_name("2.png");
+        assert!(file.file_name() == "2.png");
+    }
+
+    #[tokio::test]
+    async fn known_missing_posts() {
+        let client = Client::builder()
+            .missing_post_ttl(Some(std::time::Duration::from_secs(60)))
+            .build()
+            .expect("failed to build client");
+        client.set_token("token");
+        client.mark_missing("3qe4gdvj4j2");
+        assert!(client.is_known_missing("3qe4gdvj4j2"));
+        assert!(!client.is_known_missing("nw7w6cmlvye"));
+
+        let error = client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect_err("post should be known missing");
+        assert!(matches!(error, Error::PostKnownMissing { .. }));
+
+        let client = Client::builder()
+            .missing_post_ttl(Some(std::time::Duration::ZERO))
+            .build()
+            .expect("failed to build client");
+        client.mark_missing("3qe4gdvj4j2");
+        assert!(!client.is_known_missing("3qe4gdvj4j2"));
+
+        let client = Client::new();
+        client.mark_missing("3qe4gdvj4j2");
+        assert!(!client.is_known_missing("3qe4gdvj4j2"));
+    }
+
+    #[test]
+    fn too_many_images() {
+        let client = Client::builder()
+            .max_images_per_request(2)
+            .build()
+            .expect("failed to build client");
+
+        let mut builder = CreatePostBuilder::new();
+        for i in 0..3 {
+            builder.image(UploadPostFile::from_static(&format!("{i}.png"), b""));
+        }
+        let error = client
+            .create_post_request("token", builder)
+            .expect_err("there should be too many images");
+        assert!(matches!(
+            error,
+            Error::TooManyImages {
+                limit: 2,
+                provided: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn description_too_long() {
+        let client = Client::builder()
+            .max_description_length(Some(4))
+            .build()
+            .expect("failed to build client");
+
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_static("1.png", b"").with_description("12345"));
+        let error = client
+            .create_post_request("token", builder)
+            .expect_err("description should be too long");
+        assert!(matches!(
+            error,
+            Error::DescriptionTooLong { max: 4, actual: 5 }
+        ));
+
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_static("1.png", b"").with_description("1234"));
+        let _request = client
+            .create_post_request("token", builder)
+            .expect("description should be valid");
+    }
+
+    #[test]
+    fn update_files_bulk_order() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture).unwrap();
+        let mut files = post.images.into_vec();
+
+        let ids = vec!["kwye3cpag4b".to_string(), "nw7w6cmlvye".to_string()];
+        client::sort_files_by_ids(&mut files, &ids);
+
+        assert!(&*files[0].id == "kwye3cpag4b");
+        assert!(&*files[1].id == "nw7w6cmlvye");
+    }
+
+    #[test]
+    fn parse_scraped_user_posts_page() {
+        let html = scraper::Html::parse_document(
+            r#"<div id="app" data-page="{&quot;props&quot;:{&quot;posts&quot;:{&quot;data&quot;:[{&quot;slug&quot;:&quot;3qe4gdvj4j2&quot;},{&quot;slug&quot;:&quot;pwl7lgepyx2&quot;}],&quot;current_page&quot;:1,&quot;last_page&quot;:3,&quot;total&quot;:&quot;5&quot;}}}"></div>"#,
+        );
+        let page = ScrapedUserPostsPage::from_html(&html).expect("failed to parse page");
+
+        assert!(page.ids.len() == 2);
+        assert!(&*page.ids[0] == "3qe4gdvj4j2");
+        assert!(&*page.ids[1] == "pwl7lgepyx2");
+        assert!(page.last_page == 3);
+        assert!(page.total == Some(5));
+        assert!(page.avatar.is_none());
+    }
+
+    #[test]
+    fn parse_scraped_user_avatar() {
+        let html = scraper::Html::parse_document(
+            r#"<div id="app" data-page="{&quot;props&quot;:{&quot;user&quot;:{&quot;avatar&quot;:&quot;https://cdn.imgchest.com/avatars/LunarLandr.png&quot;},&quot;posts&quot;:{&quot;data&quot;:[],&quot;current_page&quot;:1,&quot;last_page&quot;:1}}}"></div>"#,
+        );
+        let page = ScrapedUserPostsPage::from_html(&html).expect("failed to parse page");
+        assert!(page.avatar.as_deref() == Some("https://cdn.imgchest.com/avatars/LunarLandr.png"));
+
+        let html = scraper::Html::parse_document(
+            r#"<div id="app" data-page="{&quot;props&quot;:{&quot;user&quot;:{&quot;avatar&quot;:&quot;&quot;},&quot;posts&quot;:{&quot;data&quot;:[],&quot;current_page&quot;:1,&quot;last_page&quot;:1}}}"></div>"#,
+        );
+        let page = ScrapedUserPostsPage::from_html(&html).expect("failed to parse page");
+        assert!(page.avatar.is_none());
+    }
+
+    #[test]
+    fn upload_descriptions_fill_gaps() {
+        let files = [
+            UploadPostFile::from_static("1.png", b""),
+            UploadPostFile::from_static("2.png", b"").with_description("middle"),
+            UploadPostFile::from_static("3.png", b""),
+        ];
+
+        let descriptions = client::upload_descriptions(&files).expect("missing descriptions");
+        assert!(descriptions == ["", "middle", ""]);
+
+        let files = [UploadPostFile::from_static("1.png", b"")];
+        assert!(client::upload_descriptions(&files).is_none());
+    }
+
+    #[tokio::test]
+    async fn delete_post_by_url_invalid_host() {
+        let client = Client::new();
+        client.set_token("token");
+
+        let err = client
+            .delete_post_by_url("https://example.com/v1/post/3qe4gdvj4j2")
+            .await
+            .expect_err("url should have been rejected");
+
+        assert!(matches!(err, Error::InvalidDeleteUrl));
+    }
+
+    #[test]
+    fn upload_field_names() {
+        assert!(client::IMAGES_FIELD == "images[]");
+        assert!(client::DESCRIPTIONS_FIELD == "descriptions[]");
+    }
+
+    #[tokio::test]
+    async fn metrics_count_failed_requests() {
+        let client = Client::new();
+
+        client
+            .download_file_to_writer("http://127.0.0.1:1/file.png", tokio::io::sink())
+            .await
+            .expect_err("download should fail");
+
+        let metrics = client.metrics();
+        assert!(metrics.requests == 1);
+        assert!(metrics.download_requests == 1);
+        assert!(metrics.failed_requests == 1);
+
+        client.reset_metrics();
+        assert!(client.metrics() == ClientMetrics::default());
+    }
+
+    #[test]
+    fn parse_post_ref() {
+        let post_ref: PostRef = "3qe4gdvj4j2".parse().expect("failed to parse id");
+        assert!(post_ref == PostRef::Id("3qe4gdvj4j2".into()));
+
+        let post_ref: PostRef = "https://imgchest.com/p/3qe4gdvj4j2"
+            .parse()
+            .expect("failed to parse url");
+        assert!(post_ref.id() == "3qe4gdvj4j2");
+        assert!(post_ref.secret().is_none());
+
+        let post_ref: PostRef = "https://imgchest.com/p/3qe4gdvj4j2/s3cr3t"
+            .parse()
+            .expect("failed to parse url with secret");
+        assert!(post_ref.id() == "3qe4gdvj4j2");
+        assert!(post_ref.secret() == Some("s3cr3t"));
+
+        let post_ref: PostRef = "https://cdn.imgchest.com/files/nw7w6cmlvye.png"
+            .parse()
+            .expect("failed to parse file url");
+        assert!(
+            post_ref
+                == PostRef::File {
+                    id: "nw7w6cmlvye".into(),
+                    extension: "png".into(),
+                }
+        );
+
+        let variants = [
+            "http://imgchest.com/p/3qe4gdvj4j2",
+            "https://www.imgchest.com/p/3qe4gdvj4j2",
+            "https://imgchest.com/p/3qe4gdvj4j2/",
+            "https://imgchest.com/p/3qe4gdvj4j2?ref=share#top",
+            "https://www.imgchest.com/p/3qe4gdvj4j2/?page=2",
+            "https://imgchest.com/p/3qe4gdvj4j2#comments",
+            "HTTPS://IMGCHEST.COM/p/3qe4gdvj4j2",
+            "imgchest.com/p/3qe4gdvj4j2",
+            "//www.imgchest.com/p/3qe4gdvj4j2",
+            " https://imgchest.com/p/3qe4gdvj4j2\n",
+            "https://api.imgchest.com/v1/post/3qe4gdvj4j2",
+        ];
+        for variant in variants {
+            let post_ref: PostRef = variant
+                .parse()
+                .unwrap_or_else(|error| panic!("failed to parse \"{variant}\": {error}"));
+            assert!(post_ref.post_id().ok() == Some("3qe4gdvj4j2"), "{variant}");
+        }
+
+        let post_ref: PostRef = "cdn.imgchest.com/files/nw7w6cmlvye.png"
+            .parse()
+            .expect("failed to parse file url without a scheme");
+        assert!(post_ref.id() == "nw7w6cmlvye");
+        assert!(matches!(
+            post_ref.post_id(),
+            Err(InvalidPostRefError::NotAPost)
+        ));
+
+        let error = "https://api.imgchest.com/v1/post/3qe4gdvj4j2/add"
+            .parse::<PostRef>()
+            .expect_err("path should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidPath(_)));
+
+        let error = "https://example.com/p/3qe4gdvj4j2"
+            .parse::<PostRef>()
+            .expect_err("host should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidHost(_)));
+
+        let error = "https://imgchest.com/u/LunarLandr"
+            .parse::<PostRef>()
+            .expect_err("path should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidPath(_)));
+
+        let error = "not an id"
+            .parse::<PostRef>()
+            .expect_err("id should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidId(_)));
+
+        // Forms that are not known to exist are rejected.
+        let error = "https://imgchest.com/3qe4gdvj4j2"
+            .parse::<PostRef>()
+            .expect_err("path should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidPath(_)));
+
+        let error = "https://imgchest.com/embed/3qe4gdvj4j2"
+            .parse::<PostRef>()
+            .expect_err("path should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidPath(_)));
+
+        let error = "https://i.imgchest.com/3qe4gdvj4j2"
+            .parse::<PostRef>()
+            .expect_err("host should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidHost(_)));
+
+        let error = "https://imgchest.com/p/3QE4GDVJ4J2"
+            .parse::<PostRef>()
+            .expect_err("id should be invalid");
+        assert!(matches!(error, InvalidPostRefError::InvalidId(_)));
+    }
+
+    #[tokio::test]
+    async fn download_post_filtered_none() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let post: Post = serde_json::from_value(fixture).unwrap();
+        let post = ScrapedPost::from(post);
+        let out_dir = test_dir("download_post_filtered_none");
+
+        let client = Client::new();
+        let summary = client
+            .download_post_filtered(&post, &out_dir, |_file| false)
+            .await
+            .expect("failed to download post");
+
+        assert!(summary.post_id == post.id);
+        assert!(summary.filtered == post.images.len());
+        assert!(summary.downloaded == 0);
+        assert!(summary.failed.is_empty());
+
+        std::fs::remove_dir_all(&out_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn download_file_cdn_base() {
+        let (addr, server) =
+            serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+                .await;
+
+        let client = Client::builder()
+            .cdn_base(Some(format!("http://{addr}")))
+            .build()
+            .expect("failed to build client");
+        let mut body = Vec::new();
+        let size = client
+            .download_file_to_writer("https://cdn.imgchest.com/files/nw7w6cmlvye.png", &mut body)
+            .await
+            .expect("failed to download file");
+
+        assert!(size == 5);
+        assert!(body == b"hello");
+
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("GET /files/nw7w6cmlvye.png HTTP/1.1\r\n"),
+            "{request}"
+        );
+    }
+
+    #[cfg(feature = "zip")]
+    #[tokio::test]
+    async fn download_post_to_zip() {
+        let (addr, _server) =
+            serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+                .await;
+
+        let client = Client::builder()
+            .cdn_base(Some(format!("http://{addr}")))
+            .build()
+            .expect("failed to build client");
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        post.images = post.images.into_vec().into_iter().take(1).collect();
+        post.images[0].position = 1;
+        post.images[0].link = "https://cdn.imgchest.com/files/nw7w6cmlvye.png".into();
+
+        let mut archive = Vec::new();
+        client
+            .download_post_to_zip(&post, &mut archive)
+            .await
+            .expect("failed to download post to zip");
+
+        let archive = async_zip::base::read::mem::ZipFileReader::new(archive)
+            .await
+            .expect("invalid zip archive");
+        let entries = archive.file().entries();
+        assert!(entries.len() == 2);
+        assert!(entries[0].filename().as_str().unwrap() == "post.json");
+        assert!(entries[1].filename().as_str().unwrap() == "1-nw7w6cmlvye.png");
+        assert!(entries[1].uncompressed_size() == 5);
+    }
+
+    #[tokio::test]
+    async fn with_timeout() {
+        // Respond with only part of the body, then stall.
+        let (addr, _server) =
+            serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhe")
+                .await;
+
+        let client = Client::new().with_timeout(std::time::Duration::from_millis(100));
+        let error = client
+            .download_file_to_writer(&format!("http://{addr}/file.png"), tokio::io::sink())
+            .await
+            .expect_err("download should time out");
+        assert!(
+            matches!(&error, Error::Reqwest(error) if error.is_timeout()),
+            "{error:?}"
+        );
+    }
+
+    /// A clock that sleeps by advancing its own time.
+    #[derive(Debug)]
+    struct MockClock {
+        start: std::time::Instant,
+        elapsed: std::sync::Mutex<std::time::Duration>,
+    }
+
+    impl MockClock {
+        fn new() -> Self {
+            Self {
+                start: std::time::Instant::now(),
+                elapsed: std::sync::Mutex::new(std::time::Duration::ZERO),
+            }
+        }
+
+        fn elapsed(&self) -> std::time::Duration {
+            *self.elapsed.lock().unwrap()
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> std::time::Instant {
+            self.start + self.elapsed()
+        }
+
+        fn sleep(
+            &self,
+            duration: std::time::Duration,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
+            *self.elapsed.lock().unwrap() += duration;
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn ratelimit_mock_clock() {
+        let clock = std::sync::Arc::new(MockClock::new());
+
+        // Nothing is reserved by default.
+        let token = client::TokenState::new("token", clock.clone(), client::RateLimit::default());
+        for _ in 0..60 {
+            token.ratelimit().await;
+        }
+        assert!(clock.elapsed().is_zero());
+        assert!(!token.has_remaining_requests());
+        token.ratelimit().await;
+        assert!(clock.elapsed() == std::time::Duration::from_secs(60));
+
+        let rate_limit = client::RateLimit::new(60, std::time::Duration::from_secs(60), 5);
+        let token = client::TokenState::new("token", clock.clone(), rate_limit);
+
+        // Normal requests leave the high priority reserve unused.
+        for _ in 0..55 {
+            token.ratelimit().await;
+        }
+        assert!(clock.elapsed() == std::time::Duration::from_secs(60));
+        assert!(!token.has_remaining_requests());
+
+        for _ in 0..5 {
+            token.ratelimit_with_priority(RequestPriority::High).await;
+        }
+        assert!(clock.elapsed() == std::time::Duration::from_secs(60));
+
+        // The budget is spent, so the next request waits for the next minute.
+        token.ratelimit().await;
+        assert!(clock.elapsed() == std::time::Duration::from_secs(120));
+        assert!(token.has_remaining_requests());
+
+        for _ in 0..54 {
+            token.ratelimit().await;
+        }
+        assert!(!token.has_remaining_requests());
+        token.reset_ratelimit();
+        assert!(token.has_remaining_requests());
+        assert!(clock.elapsed() == std::time::Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn configurable_ratelimit() {
+        let clock = std::sync::Arc::new(MockClock::new());
+
+        let rate_limit = client::RateLimit::new(24, std::time::Duration::from_secs(10), 2);
+        let token = client::TokenState::new("token", clock.clone(), rate_limit);
+        for _ in 0..22 {
+            token.ratelimit().await;
+        }
+        assert!(clock.elapsed().is_zero());
+        assert!(!token.has_remaining_requests());
+        token.ratelimit().await;
+        assert!(clock.elapsed() == std::time::Duration::from_secs(10));
+
+        // The reserve always leaves 1 request for normal requests.
+        let rate_limit = client::RateLimit::new(3, std::time::Duration::from_secs(10), 5);
+        let token = client::TokenState::new("token", clock.clone(), rate_limit);
+        token.ratelimit().await;
+        assert!(clock.elapsed() == std::time::Duration::from_secs(10));
+        token.ratelimit().await;
+        assert!(clock.elapsed() == std::time::Duration::from_secs(20));
+
+        // 0 requests or a zero window is unlimited.
+        for rate_limit in [
+            client::RateLimit::new(0, std::time::Duration::from_secs(10), 0),
+            client::RateLimit::new(3, std::time::Duration::ZERO, 0),
+        ] {
+            let token = client::TokenState::new("token", clock.clone(), rate_limit);
+            for _ in 0..100 {
+                token.ratelimit().await;
+            }
+            assert!(token.has_remaining_requests());
+        }
+        assert!(clock.elapsed() == std::time::Duration::from_secs(20));
+    }
+
+    #[test]
+    fn validate_upload() {
+        let client = Client::new();
+
+        client
+            .validate_upload(&UploadPostFile::from_static("image.PNG", b""))
+            .expect("png should be supported");
+
+        let err = client
+            .validate_upload(&UploadPostFile::from_static("archive.zip", b""))
+            .expect_err("zip should be unsupported");
+        assert!(matches!(err, Error::UnsupportedFormat { .. }));
+
+        let err = client
+            .validate_upload(&UploadPostFile::from_static("image", b""))
+            .expect_err("missing extension should be unsupported");
+        assert!(matches!(err, Error::UnsupportedFormat { .. }));
+    }
+
+    #[test]
+    fn scraped_post_from_html() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [
+                        {
+                            "id": "nw7w6cmlvye",
+                            "description": null,
+                            "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                            "position": 1,
+                            "created": 1_700_000_000,
+                        },
+                        {
+                            "id": "kwye3cpag4b",
+                            "description": null,
+                            "link": "https://cdn.imgchest.com/files/kwye3cpag4b.png",
+                            "position": 2,
+                            "created": "2023-11-14T22:13:20.000000Z",
+                        },
+                        {
+                            "id": "3qe4gdvj4j2",
+                            "description": null,
+                            "link": "https://cdn.imgchest.com/files/3qe4gdvj4j2.png",
+                            "position": 3,
+                        },
+                    ],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": { "id": 1234, "username": "LunarLandr" },
+                    "views": 198,
+                },
+            },
+        });
+        let html = format!(
+            r#"<div id="app" data-page="{}"></div>"#,
+            page_data.to_string().replace('"', "&quot;")
+        );
+        let post = ScrapedPost::from_html_str(&html).expect("failed to parse post");
+
+        let views =
+            parse_post_views(&scraper::Html::parse_document(&html)).expect("failed to parse views");
+        assert!(views == 198);
+
+        let expected = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert!(post.images[0].created == Some(expected));
+        assert!(post.images[1].created == Some(expected));
+        assert!(post.images[2].created.is_none());
+        assert!(post.user_id == Some(1234));
+        assert!(post.image_count == 3);
+        assert!(!post.is_incomplete());
+
+        let mut page_data = page_data;
+        page_data["props"]["post"]["image_count"] = 5.into();
+        let html = format!(
+            r#"<div id="app" data-page="{}"></div>"#,
+            page_data.to_string().replace('"', "&quot;")
+        );
+        let post = ScrapedPost::from_html_str(&html).expect("failed to parse post");
+        assert!(post.image_count == 5);
+        assert!(post.images.len() == 3);
+        assert!(post.is_incomplete());
+    }
+
+    #[test]
+    fn scraped_post_invalid_utf8() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [
+                        {
+                            "id": "nw7w6cmlvye",
+                            "description": "DESCRIPTION",
+                            "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                            "position": 1,
+                        },
+                    ],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": { "id": 1234, "username": "LunarLandr" },
+                    "views": 198,
+                },
+            },
+        });
+        let html = format!(
+            r#"<div id="app" data-page="{}"></div>"#,
+            page_data.to_string().replace('"', "&quot;")
+        );
+        let mut body = Vec::new();
+        let (start, end) = html.split_once("DESCRIPTION").unwrap();
+        body.extend_from_slice(start.as_bytes());
+        body.extend_from_slice(b"caf\xE9");
+        body.extend_from_slice(end.as_bytes());
+
+        let html = client::decode_scrape_body(body);
+        let post = ScrapedPost::from_html_str(&html).expect("failed to parse post");
+        assert!(post.images[0].description.as_deref() == Some("caf\u{FFFD}"));
+    }
+
+    #[test]
+    fn content_type_charset() {
+        assert!(client::content_type_charset("text/html; charset=UTF-8") == Some("UTF-8"));
+        assert!(
+            client::content_type_charset(r#"text/html;Charset="iso-8859-1""#) == Some("iso-8859-1")
+        );
+        assert!(client::content_type_charset("text/html").is_none());
+        assert!(client::content_type_charset("text/html; boundary=x").is_none());
+    }
+
+    #[test]
+    fn scraped_post_challenge() {
+        let html = r#"<!DOCTYPE html>
+<html>
+<head><title>Just a moment...</title></head>
+<body>
+<div class="main-wrapper">
+<noscript>Enable JavaScript and cookies to continue</noscript>
+<div class="footer">Ray ID: <code>8f0000000000abcd</code></div>
+</div>
+<script src="/cdn-cgi/challenge-platform/h/g/orchestrate/chl_page/v1"></script>
+</body>
+</html>"#;
+        let error = ScrapedPost::from_html_str(html).expect_err("should be a challenge");
+        assert!(matches!(error, InvalidScrapedPostError::Challenge));
+
+        let error = ScrapedPost::from_html_str("<html><body></body></html>")
+            .expect_err("should be missing the app div");
+        assert!(matches!(error, InvalidScrapedPostError::MissingElement(_)));
+    }
+
+    #[test]
+    fn scraped_post_migrate_from_json() {
+        let v0 = serde_json::json!({
+            "id": "3qe4gdvj4j2",
+            "title": "Donkey Kong - Video Game From The Mid 80's",
+            "username": "LunarLandr",
+            "views": 198,
+            "nsfw": false,
+            "image_count": 1,
+            "images": [
+                {
+                    "id": "nw7w6cmlvye",
+                    "description": null,
+                    "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                    "position": 1
+                }
+            ]
+        });
+        let post = ScrapedPost::migrate_from_json(v0).expect("failed to migrate v0 post");
+        assert!(&*post.id == "3qe4gdvj4j2");
+        assert!(post.images.len() == 1);
+
+        let v1 = post.to_versioned_json();
+        assert!(v1["version"] == ScrapedPost::VERSION);
+        let post = ScrapedPost::migrate_from_json(v1).expect("failed to migrate v1 post");
+        assert!(&*post.images[0].id == "nw7w6cmlvye");
+
+        let future = serde_json::json!({ "version": u64::MAX, "post": {} });
+        ScrapedPost::migrate_from_json(future).expect_err("future versions should fail");
+    }
+
     #[tokio::test]
     async fn get_scraped_post() {
         let client = Client::new();
@@ -196,6 +1685,46 @@ mod test {
         assert!(matches!(err, Error::MissingToken));
     }
 
+    #[tokio::test]
+    async fn get_full_post() {
+        let client = Client::new();
+        client.set_token(get_token());
+
+        let full_post = client
+            .get_full_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get full post");
+
+        assert!(&*full_post.post.id == "3qe4gdvj4j2");
+        assert!(full_post.scrape_error.is_none());
+        assert!(full_post.user_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn merge_full_post() {
+        let post = || serde_json::from_str::<Post>(POST_FIXTURE).unwrap();
+        let mut scraped = ScrapedPost::from(post());
+        scraped.user_id = Some(42);
+
+        let full_post = client::merge_full_post("3qe4gdvj4j2", post(), Ok(Ok(scraped)));
+        assert!(full_post.user_id == Some(42));
+        assert!(full_post.scrape_error.is_none());
+
+        let full_post =
+            client::merge_full_post("3qe4gdvj4j2", post(), Ok(Err(Error::MissingToken)));
+        assert!(&*full_post.post.id == "3qe4gdvj4j2");
+        assert!(full_post.user_id.is_none());
+        assert!(matches!(full_post.scrape_error, Some(Error::MissingToken)));
+
+        // A panicking scrape task is a scrape error, not a failure of the whole call.
+        let join_error = tokio::spawn(async { panic!("scrape panicked") })
+            .await
+            .expect_err("the task should panic");
+        let full_post = client::merge_full_post("3qe4gdvj4j2", post(), Err(join_error));
+        assert!(full_post.user_id.is_none());
+        assert!(matches!(full_post.scrape_error, Some(Error::TokioJoin(_))));
+    }
+
     #[tokio::test]
     async fn get_post() {
         let client = Client::new();
@@ -361,6 +1890,64 @@ mod test {
         assert!(matches!(err, Error::MissingImages));
     }
 
+    #[tokio::test]
+    async fn with_token() {
+        let client = Client::new();
+        let user_client = client.with_token("token");
+
+        let err = user_client
+            .update_files_bulk(Vec::new())
+            .await
+            .expect_err("should have no updates");
+        assert!(matches!(err, Error::NoUpdates));
+
+        // The original client has no token.
+        let err = client
+            .update_files_bulk(Vec::new())
+            .await
+            .expect_err("should be missing a token");
+        assert!(matches!(err, Error::MissingToken));
+    }
+
+    #[tokio::test]
+    async fn with_token_shares_ratelimit() {
+        let clock = std::sync::Arc::new(MockClock::new());
+        let client = Client::builder()
+            .clock(clock.clone())
+            .requests_per_minute(1)
+            .build()
+            .expect("failed to build client");
+
+        let first = client.with_token("user").get_token().unwrap();
+        first.ratelimit().await;
+
+        // A new handle for the same token does not get a new budget.
+        let second = client.with_token("user").get_token().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert!(!second.has_remaining_requests());
+
+        let other = client.with_token("other").get_token().unwrap();
+        assert!(other.has_remaining_requests());
+
+        client.reset_rate_limit();
+        assert!(second.has_remaining_requests());
+        assert!(clock.elapsed().is_zero());
+    }
+
+    #[tokio::test]
+    async fn update_files_bulk_no_updates() {
+        let client = Client::new();
+        // This is rejected before any request, so any token works.
+        client.set_token("token");
+
+        let err = client
+            .update_files_bulk(Vec::new())
+            .await
+            .expect_err("should have no updates");
+
+        assert!(matches!(err, Error::NoUpdates));
+    }
+
     #[tokio::test]
     async fn create_post_missing_images() {
         let client = Client::new();
@@ -404,4 +1991,265 @@ mod test {
 
         assert!(matches!(err, Error::MissingDescription));
     }
+
+    #[tokio::test]
+    async fn archive_posts_resume() {
+        let dir = test_dir("archive_posts_resume");
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let progress_path = dir.join("progress.json");
+
+        assert!(ArchiveProgress::load(&progress_path)
+            .await
+            .expect("failed to load missing progress")
+            .posts
+            .is_empty());
+
+        let progress = ArchiveProgress {
+            posts: vec![ArchivedPost {
+                id: "3qe4gdvj4j2".into(),
+                files: vec![ArchivedFile {
+                    id: "nw7w6cmlvye".into(),
+                    file_name: "nw7w6cmlvye.png".into(),
+                    sha256: "00".into(),
+                }],
+            }],
+        };
+        progress.save(&progress_path).await.unwrap();
+        let loaded = ArchiveProgress::load(&progress_path).await.unwrap();
+        assert!(loaded.is_completed("3qe4gdvj4j2"));
+        assert!(!loaded.is_completed("other"));
+        assert!(&*loaded.posts[0].files[0].file_name == "nw7w6cmlvye.png");
+
+        // An interrupted append leaves an incomplete last line, which is ignored.
+        let mut data = tokio::fs::read(&progress_path).await.unwrap();
+        data.extend_from_slice(br#"{"id":"kwye3cpag4b","fi"#);
+        tokio::fs::write(&progress_path, &data).await.unwrap();
+        let loaded = ArchiveProgress::load(&progress_path).await.unwrap();
+        assert!(loaded.posts.len() == 1);
+        assert!(!loaded.is_completed("kwye3cpag4b"));
+
+        // An invalid line before the last one is an error.
+        data.extend_from_slice(b"\n{}\n");
+        tokio::fs::write(&progress_path, &data).await.unwrap();
+        assert!(ArchiveProgress::load(&progress_path).await.is_err());
+        progress.save(&progress_path).await.unwrap();
+
+        // Completed posts are skipped before any request is made.
+        let client = Client::new();
+        let summary = client
+            .archive_posts(
+                ["3qe4gdvj4j2", "3qe4gdvj4j2"],
+                &dir,
+                Some(progress_path.as_path()),
+            )
+            .await
+            .expect("failed to archive posts");
+        assert!(summary.skipped == 1);
+        assert!(summary.downloaded.is_empty());
+        assert!(summary.failed.is_empty());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[test]
+    fn unexpected_response_shape() {
+        let body = br#"{"post":{"id":"3qe4gdvj4j2"}}"#;
+        let error = client::parse_api_json::<ApiResponse<Post>>(body)
+            .expect_err("should not match the expected shape");
+        assert!(
+            matches!(&error, Error::UnexpectedResponseShape { body: raw, .. } if raw.as_bytes() == body),
+            "{error:?}"
+        );
+
+        let error =
+            client::parse_api_json::<ApiResponse<Post>>(b"<html>").expect_err("should not be json");
+        assert!(
+            matches!(&error, Error::UnexpectedResponseShape { body, .. } if body == "<html>"),
+            "{error:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn download_empty_post() {
+        let dir = test_dir("download_empty_post");
+
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        post.images = Box::default();
+
+        let client = Client::new();
+        let summary = client
+            .download_post_filtered(&post, &dir, |_file| true)
+            .await
+            .expect("empty post should not be an error");
+        assert!(summary.post_id == post.id);
+        assert!(summary.downloaded == 0);
+        assert!(summary.skipped == 0);
+        assert!(summary.filtered == 0);
+        assert!(summary.failed.is_empty());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_interceptor() {
+        #[derive(Debug)]
+        struct CorrelationId;
+
+        impl RequestInterceptor for CorrelationId {
+            fn intercept(&self, request: &mut reqwest::Request) {
+                request.headers_mut().insert(
+                    "x-correlation-id",
+                    reqwest::header::HeaderValue::from_static("abc123"),
+                );
+            }
+        }
+
+        let (addr, server) =
+            serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+
+        let client = Client::builder()
+            .interceptor(std::sync::Arc::new(CorrelationId))
+            .build()
+            .expect("failed to build client");
+        client
+            .download_file_to_writer(&format!("http://{addr}/file.png"), tokio::io::sink())
+            .await
+            .expect("failed to download file");
+
+        let request = server.await.unwrap();
+        assert!(
+            request.contains("x-correlation-id: abc123\r\n"),
+            "{request}"
+        );
+    }
+
+    #[test]
+    fn post_cover() {
+        let cover: ApiPostCover =
+            serde_json::from_str(POST_FIXTURE).expect("failed to parse cover");
+        assert!(cover.link.as_deref() == Some("https://cdn.imgchest.com/files/nw7w6cmlvye.png"));
+
+        let mut fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        fixture["images"][0]["position"] = 3.into();
+        let cover: ApiPostCover = serde_json::from_value(fixture.clone()).unwrap();
+        assert!(cover.link.as_deref() == Some("https://cdn.imgchest.com/files/kwye3cpag4b.png"));
+
+        fixture["images"] = serde_json::json!([]);
+        let cover: ApiPostCover = serde_json::from_value(fixture).unwrap();
+        assert!(cover.link.is_none());
+    }
+
+    #[test]
+    fn file_kind() {
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        assert!(post.images[0].kind() == FileKind::Image);
+
+        let file = &mut post.images[0];
+        file.link = "https://cdn.imgchest.com/files/nw7w6cmlvye.MP4?x=1".into();
+        assert!(file.kind() == FileKind::Video);
+        file.link = "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into();
+        assert!(file.kind() == FileKind::Other);
+        file.link = "https://cdn.imgchest.com/files/".into();
+        assert!(file.kind() == FileKind::Other);
+        assert!(file.file_name().is_none());
+        file.link = "not a url".into();
+        assert!(file.kind() == FileKind::Other);
+    }
+
+    #[tokio::test]
+    async fn download_post_skips_other_files() {
+        let dir = test_dir("download_post_skips_other_files");
+
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        post.images = post.images.into_vec().into_iter().take(1).collect();
+        post.images[0].link = "https://www.youtube.com/embed/dQw4w9WgXcQ".into();
+
+        let client = Client::new();
+        let summary = client
+            .download_post_filtered(&post, &dir, |_file| true)
+            .await
+            .expect("failed to download post");
+        assert!(summary.unsupported == 1);
+        assert!(summary.downloaded == 0);
+        assert!(summary.failed.is_empty());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn post_manifest_skips_other_files() {
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        post.images = post.images.into_vec().into_iter().take(1).collect();
+        post.images[0].link = "https://www.youtube.com/embed/dQw4w9WgXcQ".into();
+
+        let client = Client::new();
+        let manifest = client
+            .build_scraped_post_manifest(post)
+            .await
+            .expect("failed to build manifest");
+        assert!(&*manifest.id == "3qe4gdvj4j2");
+        assert!(manifest.files.is_empty());
+    }
+
+    #[test]
+    fn upload_order_mismatch() {
+        let fixture: serde_json::Value = serde_json::from_str(POST_FIXTURE).unwrap();
+        let files = |files: &[(u32, Option<&str>)]| -> Vec<PostFile> {
+            files
+                .iter()
+                .map(|(position, original_name)| {
+                    let mut file = fixture["images"][0].clone();
+                    file["position"] = (*position).into();
+                    file["original_name"] = (*original_name).into();
+                    serde_json::from_value(file).unwrap()
+                })
+                .collect()
+        };
+        let file_names: Vec<_> = (1..=3).map(|i| format!("{i}.png")).collect();
+
+        // Files are compared in position order, not list order.
+        let post_files = files(&[(3, Some("3.png")), (1, Some("1.png")), (2, Some("2.png"))]);
+        assert!(client::find_upload_order_mismatch(&post_files, &file_names).is_none());
+
+        let post_files = files(&[(1, Some("1.png")), (2, Some("3.png")), (3, Some("2.png"))]);
+        assert!(client::find_upload_order_mismatch(&post_files, &file_names) == Some(1));
+
+        let post_files = files(&[(1, Some("1.png")), (2, Some("2.png"))]);
+        assert!(client::find_upload_order_mismatch(&post_files, &file_names) == Some(2));
+
+        let post_files = files(&[(1, Some("1.png")), (2, None), (3, Some("2.png"))]);
+        assert!(client::find_upload_order_mismatch(&post_files, &file_names).is_none());
+    }
+
+    #[tokio::test]
+    async fn refuse_nsfw_post() {
+        let dir = test_dir("refuse_nsfw_post");
+
+        let mut post = ScrapedPost::from(serde_json::from_str::<Post>(POST_FIXTURE).unwrap());
+        post.nsfw = true;
+
+        let client = Client::builder()
+            .allow_nsfw(false)
+            .build()
+            .expect("failed to build client");
+        let error = client
+            .download_post_filtered(&post, &dir, |_file| true)
+            .await
+            .expect_err("nsfw post should be refused");
+        assert!(
+            matches!(&error, Error::NsfwPostRefused { id } if **id == *post.id),
+            "{error:?}"
+        );
+        assert!(!dir.exists());
+
+        // Files are filtered out, so nothing is requested.
+        post.nsfw = false;
+        let summary = client
+            .download_post_filtered(&post, &dir, |_file| false)
+            .await
+            .expect("sfw post should be allowed");
+        assert!(summary.filtered == 2);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
 }
@@ -1,13 +1,19 @@
 mod post;
 mod scraped_post;
+mod scraped_user;
 mod user;
 
+pub(crate) use self::post::Cover as ApiPostCover;
 pub use self::post::File as PostFile;
+pub use self::post::Meta as PostMeta;
 pub use self::post::Post;
 pub use self::post::Privacy as PostPrivacy;
+pub(crate) use self::scraped_post::parse_post_views;
 pub use self::scraped_post::File as ScrapedPostFile;
 pub use self::scraped_post::FromHtmlError as InvalidScrapedPostError;
 pub use self::scraped_post::ScrapedPost;
+pub use self::scraped_user::ScrapedUser;
+pub(crate) use self::scraped_user::ScrapedUserPostsPage;
 pub use self::user::User;
 
 /// A request for updating files in bulk.
@@ -35,6 +41,32 @@ pub struct FileUpdate {
 pub(crate) struct ApiResponse<T> {
     /// The data payload
     pub data: T,
+
+    /// Pagination links, if the response is paginated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<ApiPaginationLinks>,
+
+    /// Pagination metadata, if the response is paginated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ApiPaginationMeta>,
+}
+
+/// The pagination links of a paginated api response
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ApiPaginationLinks {
+    /// The url of the next page
+    #[serde(default)]
+    pub next: Option<Box<str>>,
+}
+
+/// The pagination metadata of a paginated api response
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ApiPaginationMeta {
+    /// The current page, starting at 1
+    pub current_page: u32,
+
+    /// The last page
+    pub last_page: u32,
 }
 
 /// The response for when the api completed something
@@ -48,6 +80,185 @@ pub(crate) struct ApiCompletedResponse {
     pub message: Option<Box<str>>,
 }
 
+/// The host of the cdn that serves post files.
+pub(crate) const CDN_HOST: &str = "cdn.imgchest.com";
+
+/// File extensions that are classified as videos.
+const VIDEO_EXTENSIONS: &[&str] = &["mov", "mp4", "webm"];
+
+/// The kind of a post file, derived from its link.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FileKind {
+    /// An image on the cdn
+    Image,
+
+    /// A video on the cdn
+    Video,
+
+    /// A link that is not a file on the cdn, like an embed or an external link
+    ///
+    /// These may not be downloadable, and their last path segment may not be a file name.
+    Other,
+}
+
+/// Classify a file link.
+///
+/// Links are only trusted to be files if they are on the cdn and have a file name with an extension.
+pub(crate) fn link_kind(link: &str) -> FileKind {
+    let is_cdn = reqwest::Url::parse(link).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https") && url.host_str() == Some(CDN_HOST)
+    });
+    if !is_cdn {
+        return FileKind::Other;
+    }
+
+    match link_extension(link) {
+        Some(extension)
+            if VIDEO_EXTENSIONS
+                .iter()
+                .any(|video| extension.eq_ignore_ascii_case(video)) =>
+        {
+            FileKind::Video
+        }
+        Some(_) => FileKind::Image,
+        None => FileKind::Other,
+    }
+}
+
+/// Get the file name of a file link, which is its last path segment.
+///
+/// Any query or fragment is ignored.
+pub(crate) fn link_file_name(link: &str) -> Option<&str> {
+    let path = link.split(['?', '#']).next().unwrap_or(link);
+    path.rsplit('/')
+        .next()
+        .filter(|file_name| !file_name.is_empty())
+}
+
+/// Get the extension of a file link, without the leading dot.
+pub(crate) fn link_extension(link: &str) -> Option<&str> {
+    let (stem, extension) = link_file_name(link)?.rsplit_once('.')?;
+    Some(extension).filter(|extension| !stem.is_empty() && !extension.is_empty())
+}
+
+/// (De)serialize timestamps in the format the API uses.
+///
+/// `time::serde::iso8601` serializes years with 6 digits and a sign,
+/// which the API never sends.
+///
+/// Deserialization is lenient, accepting any subsecond precision and any offset.
+/// Timestamps are normalized to UTC.
+pub(crate) mod api_timestamp {
+    use serde::de::Error;
+    use std::borrow::Cow;
+    use time::format_description::well_known::Iso8601;
+    use time::format_description::well_known::Rfc3339;
+    use time::macros::format_description;
+    use time::OffsetDateTime;
+    use time::PrimitiveDateTime;
+    use time::UtcOffset;
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value: Cow<str> = serde::Deserialize::deserialize(deserializer)?;
+        let value = value.trim();
+
+        let parsed = OffsetDateTime::parse(value, &Iso8601::DEFAULT)
+            .or_else(|_| OffsetDateTime::parse(value, &Rfc3339))
+            .or_else(|error| {
+                // Timestamps without an offset are in UTC.
+                let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+                PrimitiveDateTime::parse(value, &format)
+                    .map(PrimitiveDateTime::assume_utc)
+                    .map_err(|_| error)
+            })
+            .map_err(D::Error::custom)?;
+
+        Ok(parsed.to_offset(UtcOffset::UTC))
+    }
+
+    pub(crate) fn serialize<S>(value: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::Error;
+
+        let format = format_description!(
+            "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:6]Z"
+        );
+        let value = value
+            .to_offset(UtcOffset::UTC)
+            .format(&format)
+            .map_err(S::Error::custom)?;
+        serializer.serialize_str(&value)
+    }
+}
+
+/// (De)serialize optional timestamps in the format the API uses.
+pub(crate) mod api_timestamp_option {
+    use time::OffsetDateTime;
+
+    #[derive(serde::Deserialize)]
+    struct Timestamp(#[serde(with = "super::api_timestamp")] OffsetDateTime);
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value: Option<Timestamp> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(value.map(|value| value.0))
+    }
+
+    pub(crate) fn serialize<S>(
+        value: &Option<OffsetDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match value {
+            Some(value) => super::api_timestamp::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Deserialize a count that may be sent as an integer or as a string.
+pub(crate) fn deserialize_count<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Count<'a> {
+        Int(u64),
+        Str(std::borrow::Cow<'a, str>),
+    }
+
+    match serde::Deserialize::deserialize(deserializer)? {
+        Count::Int(value) => Ok(value),
+        Count::Str(value) => value.trim().parse().map_err(D::Error::custom),
+    }
+}
+
+/// Deserialize an optional count that may be sent as an integer or as a string.
+///
+/// Use with `#[serde(default)]` so that a missing field is `None`.
+pub(crate) fn deserialize_count_option<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    struct Count(#[serde(deserialize_with = "deserialize_count")] u64);
+
+    let value: Option<Count> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.map(|value| value.0))
+}
+
 mod from_str_to_str {
     use serde::de::Error;
     use std::borrow::Cow;
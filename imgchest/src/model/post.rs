@@ -10,6 +10,10 @@ pub struct Post {
     /// The post title
     pub title: Option<Box<str>>,
 
+    /// The post description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Box<str>>,
+
     /// The post author's username
     pub username: Box<str>,
 
@@ -20,6 +24,9 @@ pub struct Post {
     pub report_status: i32,
 
     /// The number of views
+    ///
+    /// This is the server's view counter.
+    /// It is not documented whether it counts unique viewers or every view.
     pub views: u64,
 
     /// Whether the post is nsfw
@@ -27,23 +34,99 @@ pub struct Post {
     pub nsfw: bool,
 
     /// The number of images
+    ///
+    /// This is the server's count and is authoritative.
+    /// If it is larger than the length of `images`, `images` is incomplete.
     pub image_count: u64,
 
     /// The time this was created
-    #[serde(with = "time::serde::iso8601")]
+    #[serde(with = "crate::model::api_timestamp")]
     pub created: OffsetDateTime,
 
     /// The files of this post
+    #[serde(deserialize_with = "deserialize_files")]
     pub images: Box<[File]>,
 
     /// The url to delete this post
     ///
     /// Only present if the current user owns this post.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub delete_url: Option<Box<str>>,
     // #[serde(flatten)]
     // extra: std::collections::HashMap<Box<str>, serde_json::Value>,
 }
 
+impl std::fmt::Display for Post {
+    /// Format a one-line summary.
+    ///
+    /// The id is used if the post has no title.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} by {} ({} images, {} views)",
+            self.title.as_deref().unwrap_or(&self.id),
+            self.username,
+            self.image_count,
+            self.views
+        )
+    }
+}
+
+/// The top-level fields of an API post object
+///
+/// Unlike [`Post`], the `images` array is skipped while parsing instead of being deserialized,
+/// so no file objects are allocated.
+/// This is useful for list views over many posts.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Meta {
+    /// The post id
+    pub id: Box<str>,
+
+    /// The post title
+    pub title: Option<Box<str>>,
+
+    /// The post author's username
+    pub username: Box<str>,
+
+    /// The privacy of the post
+    pub privacy: Privacy,
+
+    /// The number of views
+    pub views: u64,
+
+    /// Whether the post is nsfw
+    #[serde(with = "u8_to_bool")]
+    pub nsfw: bool,
+
+    /// The number of images
+    pub image_count: u64,
+    // Unknown fields, including `images`, are skipped by serde without allocating.
+}
+
+impl From<&Post> for Meta {
+    fn from(post: &Post) -> Self {
+        Self {
+            id: post.id.clone(),
+            title: post.title.clone(),
+            username: post.username.clone(),
+            privacy: post.privacy,
+            views: post.views,
+            nsfw: post.nsfw,
+            image_count: post.image_count,
+        }
+    }
+}
+
+/// The cover of an API post object
+///
+/// Only the link of the cover file is kept while parsing the post's files.
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct Cover {
+    /// The link to the file with the lowest position, if the post has files
+    #[serde(rename = "images", deserialize_with = "deserialize_cover")]
+    pub link: Option<Box<str>>,
+}
+
 /// An API file of a post
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct File {
@@ -59,29 +142,62 @@ pub struct File {
     /// The position of the image in the post.
     ///
     /// Starts at 1.
+    /// Positions may have gaps if files were deleted from the post.
+    ///
+    /// If the API omits this or returns 0,
+    /// it is derived from the index of the file in the post's file list instead.
     pub position: NonZeroU32,
 
     /// The time this image was created.
-    #[serde(with = "time::serde::iso8601")]
+    #[serde(with = "crate::model::api_timestamp")]
     pub created: OffsetDateTime,
 
     /// The original name of the image.
     ///
     /// Only present if the current user owns this image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub original_name: Option<Box<str>>,
     // #[serde(flatten)]
     // extra: std::collections::HashMap<Box<str>, serde_json::Value>,
 }
 
+impl File {
+    /// Get the file name from the link.
+    ///
+    /// This is the last path segment of the link.
+    pub fn file_name(&self) -> Option<&str> {
+        crate::model::link_file_name(&self.link)
+    }
+
+    /// Get the file extension from the link, without the leading dot.
+    ///
+    /// This is `None` if the file name has no extension.
+    pub fn extension(&self) -> Option<&str> {
+        crate::model::link_extension(&self.link)
+    }
+
+    /// Classify this file from its link.
+    ///
+    /// Files that are not on the cdn, or have no file name with an extension, are [`FileKind::Other`].
+    pub fn kind(&self) -> crate::FileKind {
+        crate::model::link_kind(&self.link)
+    }
+}
+
 /// The post privacy
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum Privacy {
+    /// The post is listed publicly and anyone can view it.
     #[serde(rename = "public")]
     Public,
 
+    /// The post is unlisted.
+    ///
+    /// Anyone with the link can view it.
     #[serde(rename = "hidden")]
     Hidden,
 
+    /// The post can only be viewed by its owner.
     #[serde(rename = "secret")]
     Secret,
 }
@@ -95,6 +211,118 @@ impl Privacy {
             Self::Secret => "secret",
         }
     }
+
+    /// Returns true if this post is publicly visible.
+    ///
+    /// Only public posts are. Hidden posts can still be viewed with a link,
+    /// but they are not listed and should not be treated as public content.
+    pub fn is_publicly_visible(self) -> bool {
+        matches!(self, Self::Public)
+    }
+}
+
+/// Deserialize files one at a time,
+/// so that errors point at the file that failed.
+///
+/// Missing or zero positions are replaced with the file's index + 1.
+fn deserialize_files<'de, D>(deserializer: D) -> Result<Box<[File]>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    let values: Vec<serde_json::Value> = Deserialize::deserialize(deserializer)?;
+
+    let mut files = Vec::with_capacity(values.len());
+    let mut errors = Vec::new();
+    for (index, mut value) in values.into_iter().enumerate() {
+        let id = value.get("id").and_then(|id| id.as_str()).map(String::from);
+
+        if let Some(object) = value.as_object_mut() {
+            let needs_position = match object.get("position") {
+                None | Some(serde_json::Value::Null) => true,
+                Some(position) => position.as_u64() == Some(0),
+            };
+            if needs_position {
+                let position = index + 1;
+                match id.as_deref() {
+                    Some(id) => tracing::warn!(
+                        "image {index} (id {id}) has a missing or zero position, using {position}"
+                    ),
+                    None => tracing::warn!(
+                        "image {index} has a missing or zero position, using {position}"
+                    ),
+                }
+                object.insert("position".into(), position.into());
+            }
+        }
+
+        match File::deserialize(value) {
+            Ok(file) => files.push(file),
+            Err(error) => match id {
+                Some(id) => errors.push(format!("image {index} (id {id}): {error}")),
+                None => errors.push(format!("image {index}: {error}")),
+            },
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(D::Error::custom(errors.join("; ")));
+    }
+
+    Ok(files.into())
+}
+
+/// Deserialize the link of the file with the lowest position.
+///
+/// Files are visited one at a time, and missing or zero positions are treated as the file's index + 1,
+/// like [`deserialize_files`].
+fn deserialize_cover<'de, D>(deserializer: D) -> Result<Option<Box<str>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    struct CoverFile {
+        link: Box<str>,
+        #[serde(default)]
+        position: Option<u64>,
+    }
+
+    struct CoverVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for CoverVisitor {
+        type Value = Option<Box<str>>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a sequence of files")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let mut cover: Option<(u64, Box<str>)> = None;
+            let mut index = 0;
+            while let Some(file) = seq.next_element::<CoverFile>()? {
+                index += 1;
+                let position = file
+                    .position
+                    .filter(|position| *position != 0)
+                    .unwrap_or(index);
+                if cover
+                    .as_ref()
+                    .is_none_or(|(cover_position, _)| position < *cover_position)
+                {
+                    cover = Some((position, file.link));
+                }
+            }
+
+            Ok(cover.map(|(_, link)| link))
+        }
+    }
+
+    deserializer.deserialize_seq(CoverVisitor)
 }
 
 mod u8_to_bool {
@@ -1,11 +1,19 @@
 use once_cell::sync::Lazy;
 use scraper::Html;
 use scraper::Selector;
+use time::OffsetDateTime;
 
 static APP_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("#app").unwrap());
+static CHALLENGE_SELECTOR: Lazy<Selector> = Lazy::new(|| {
+    Selector::parse(
+        "script[src*=\"/cdn-cgi/challenge-platform/\"], #challenge-form, #challenge-error-text, #cf-wrapper, .ray-id",
+    )
+    .unwrap()
+});
 
 /// An error that may occur while parsing a post
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum FromHtmlError {
     #[error("missing {0}")]
     MissingElement(&'static str),
@@ -15,6 +23,12 @@ pub enum FromHtmlError {
 
     #[error("invalid data page")]
     InvalidDataPage(serde_json::Error),
+
+    /// The page is an anti-bot challenge instead of the requested page.
+    ///
+    /// Back off, or retry with a solved challenge cookie.
+    #[error("received an anti-bot challenge page")]
+    Challenge,
 }
 
 /// A Post
@@ -26,21 +40,40 @@ pub struct ScrapedPost {
     /// The title of the post
     pub title: Box<str>,
 
+    /// The description of the post
+    #[serde(default)]
+    pub description: Option<Box<str>>,
+
     /// The author of the post
     pub username: Box<str>,
 
+    /// The id of the author of the post
+    ///
+    /// Unlike the username, this does not change.
+    /// This is `None` if the page did not include it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<u64>,
+
     // /// The post privacy
     // pub privacy: String,
 
     // /// ?
     // pub report_status: u32,
     /// The number of views
+    ///
+    /// This is the server's view counter.
+    /// It is not documented whether it counts unique viewers or every view.
     pub views: u64,
 
     /// Whether this is nsfw
     pub nsfw: bool,
 
     /// The number of images
+    ///
+    /// This is the server's count,
+    /// which may be larger than the number of files embedded in the page.
+    /// See [`ScrapedPost::is_incomplete`].
+    /// If the page did not include a count, this is the number of embedded files.
     pub image_count: u64,
 
     // /// The timestamp of post creation
@@ -49,23 +82,110 @@ pub struct ScrapedPost {
     pub images: Box<[File]>,
 }
 
+impl From<crate::Post> for ScrapedPost {
+    fn from(post: crate::Post) -> Self {
+        Self {
+            id: post.id,
+            title: post.title.unwrap_or_default(),
+            description: post.description,
+            username: post.username,
+            user_id: None,
+            views: post.views,
+            nsfw: post.nsfw,
+            image_count: post.image_count,
+            images: post.images.into_vec().into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for ScrapedPost {
+    /// Format a one-line summary.
+    ///
+    /// The id is used if the post has no title.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let title = if self.title.is_empty() {
+            &self.id
+        } else {
+            &self.title
+        };
+        write!(
+            f,
+            "{title} by {} ({} images, {} views)",
+            self.username, self.image_count, self.views
+        )
+    }
+}
+
 impl ScrapedPost {
+    /// The current version of the versioned json format.
+    pub const VERSION: u64 = 1;
+
+    /// Serialize this into a versioned json envelope, for long-lived archives.
+    ///
+    /// The envelope has the form `{"version": VERSION, "post": { ... }}`.
+    pub fn to_versioned_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "version": Self::VERSION,
+            "post": self,
+        })
+    }
+
+    /// Parse this from json written by any version of this library.
+    ///
+    /// This accepts both the versioned envelope and bare unversioned posts,
+    /// filling in fields that did not exist yet with defaults.
+    pub fn migrate_from_json(mut value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        use serde::de::Error;
+
+        let version = match value.get("version") {
+            Some(version) => Some(
+                version
+                    .as_u64()
+                    .ok_or_else(|| serde_json::Error::custom("invalid version"))?,
+            ),
+            None => None,
+        };
+
+        match version {
+            // Version 0 is an unversioned, bare post.
+            None => serde_json::from_value(value),
+            Some(1) => {
+                let post = value
+                    .get_mut("post")
+                    .map(serde_json::Value::take)
+                    .ok_or_else(|| serde_json::Error::missing_field("post"))?;
+                serde_json::from_value(post)
+            }
+            Some(version) => Err(serde_json::Error::custom(format!(
+                "unsupported version {version}"
+            ))),
+        }
+    }
+
+    /// Returns true if `images` has fewer files than the server's `image_count`.
+    ///
+    /// Large posts may not embed all of their files in the page.
+    pub fn is_incomplete(&self) -> bool {
+        u64::try_from(self.images.len()).unwrap() < self.image_count
+    }
+
+    /// Parse this from the html text of a post page.
+    ///
+    /// This is the parser used by [`Client::get_scraped_post`](crate::Client::get_scraped_post),
+    /// and can be used with html from [`Client::fetch_post_html`](crate::Client::fetch_post_html).
+    /// Parsing is cpu-bound and may block for large pages.
+    pub fn from_html_str(html: &str) -> Result<Self, FromHtmlError> {
+        let html = Html::parse_document(html);
+        Self::from_html(&html)
+    }
+
     /// Parse this from html
     pub(crate) fn from_html(html: &Html) -> Result<Self, FromHtmlError> {
-        // Implement:
-        // JSON.parse(document.getElementById('app').getAttribute('data-page'))
-        let app_element = html
-            .select(&APP_SELECTOR)
-            .next()
-            .ok_or(FromHtmlError::MissingElement("app div"))?;
-        let data_page_attr = app_element
-            .attr("data-page")
-            .ok_or(FromHtmlError::MissingAttribute("data-page"))?;
-        let page_data: PageData =
-            serde_json::from_str(data_page_attr).map_err(FromHtmlError::InvalidDataPage)?;
+        let page_data: PageData = parse_page_data(html)?;
 
         // Overflowing a u64 with image entries is impossible.
-        let image_count = u64::try_from(page_data.props.post.files.len()).unwrap();
+        let num_files = u64::try_from(page_data.props.post.files.len()).unwrap();
+        let image_count = page_data.props.post.image_count.unwrap_or(num_files);
         let images: Vec<_> = page_data
             .props
             .post
@@ -76,12 +196,15 @@ impl ScrapedPost {
                 description: file.description,
                 link: file.link,
                 position: file.position,
+                created: file.created,
             })
             .collect();
         Ok(Self {
             id: page_data.props.post.slug,
             title: page_data.props.post.title,
+            description: page_data.props.post.description,
             username: page_data.props.post.user.username,
+            user_id: page_data.props.post.user.id,
             views: page_data.props.post.views,
             nsfw: page_data.props.post.nsfw != 0,
             image_count,
@@ -90,6 +213,49 @@ impl ScrapedPost {
     }
 }
 
+/// Parse only the view count of a post page.
+///
+/// This skips building the post and its files.
+pub(crate) fn parse_post_views(html: &Html) -> Result<u64, FromHtmlError> {
+    #[derive(serde::Deserialize)]
+    struct PageData {
+        props: PageDataProps,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct PageDataProps {
+        post: PageDataPost,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct PageDataPost {
+        views: u64,
+    }
+
+    let page_data: PageData = parse_page_data(html)?;
+    Ok(page_data.props.post.views)
+}
+
+/// Parse the page data embedded in the app div.
+pub(super) fn parse_page_data<T>(html: &Html) -> Result<T, FromHtmlError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    // Implement:
+    // JSON.parse(document.getElementById('app').getAttribute('data-page'))
+    let app_element = match html.select(&APP_SELECTOR).next() {
+        Some(app_element) => app_element,
+        None if html.select(&CHALLENGE_SELECTOR).next().is_some() => {
+            return Err(FromHtmlError::Challenge);
+        }
+        None => return Err(FromHtmlError::MissingElement("app div")),
+    };
+    let data_page_attr = app_element
+        .attr("data-page")
+        .ok_or(FromHtmlError::MissingAttribute("data-page"))?;
+    serde_json::from_str(data_page_attr).map_err(FromHtmlError::InvalidDataPage)
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct PageData {
     props: PageDataProps,
@@ -102,7 +268,11 @@ struct PageDataProps {
 
 #[derive(Debug, serde::Deserialize)]
 struct PageDataPost {
+    #[serde(default)]
+    description: Option<Box<str>>,
     files: Vec<PageDataFile>,
+    #[serde(default)]
+    image_count: Option<u64>,
     nsfw: u8,
     slug: Box<str>,
     title: Box<str>,
@@ -113,6 +283,8 @@ struct PageDataPost {
 #[derive(Debug, serde::Deserialize)]
 struct PageDataUser {
     username: Box<str>,
+    #[serde(default, deserialize_with = "crate::model::deserialize_count_option")]
+    id: Option<u64>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -121,6 +293,8 @@ struct PageDataFile {
     description: Option<Box<str>>,
     link: Box<str>,
     position: u32,
+    #[serde(default, deserialize_with = "deserialize_page_timestamp")]
+    created: Option<OffsetDateTime>,
 }
 
 /// A post file
@@ -139,6 +313,93 @@ pub struct File {
     ///
     /// Starts at 1.
     pub position: u32,
-    // /// The file creation time
-    // pub created: u32,
+
+    /// The time this file was created.
+    ///
+    /// This is `None` if the page did not include it,
+    /// or if this was loaded from an archive made before this field existed.
+    #[serde(
+        default,
+        with = "crate::model::api_timestamp_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<OffsetDateTime>,
+}
+
+impl File {
+    /// Get the file name from the link.
+    ///
+    /// This is the last path segment of the link.
+    pub fn file_name(&self) -> Option<&str> {
+        crate::model::link_file_name(&self.link)
+    }
+
+    /// Get the file extension from the link, without the leading dot.
+    ///
+    /// This is `None` if the file name has no extension.
+    pub fn extension(&self) -> Option<&str> {
+        crate::model::link_extension(&self.link)
+    }
+
+    /// Classify this file from its link.
+    ///
+    /// Files that are not on the cdn, or have no file name with an extension, are [`FileKind::Other`].
+    pub fn kind(&self) -> crate::FileKind {
+        crate::model::link_kind(&self.link)
+    }
+}
+
+impl From<crate::PostFile> for File {
+    fn from(file: crate::PostFile) -> Self {
+        Self {
+            id: file.id,
+            description: file.description,
+            link: file.link,
+            position: file.position.get(),
+            created: Some(file.created),
+        }
+    }
+}
+
+/// Deserialize a page timestamp,
+/// which may be a unix timestamp in seconds or a string.
+fn deserialize_page_timestamp<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use std::borrow::Cow;
+    use time::format_description::well_known::Iso8601;
+    use time::macros::format_description;
+    use time::PrimitiveDateTime;
+
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp<'a> {
+        Unix(i64),
+        Str(Cow<'a, str>),
+    }
+
+    let value: Option<Timestamp> = serde::Deserialize::deserialize(deserializer)?;
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    match value {
+        Timestamp::Unix(value) => OffsetDateTime::from_unix_timestamp(value)
+            .map(Some)
+            .map_err(D::Error::custom),
+        Timestamp::Str(value) => {
+            if let Ok(value) = OffsetDateTime::parse(&value, &Iso8601::DEFAULT) {
+                return Ok(Some(value));
+            }
+
+            // Timestamps without an offset are in UTC.
+            let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+            PrimitiveDateTime::parse(&value, &format)
+                .map(|value| Some(value.assume_utc()))
+                .map_err(D::Error::custom)
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use super::scraped_post::parse_page_data;
+use super::scraped_post::FromHtmlError;
+use scraper::Html;
+
+/// A user, scraped from their profile.
+///
+/// Unlike [`User`](crate::User),
+/// the comment count and creation time are not shown on the profile,
+/// so they are not available.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScrapedUser {
+    /// The user name
+    pub name: Box<str>,
+
+    /// The number of posts
+    ///
+    /// If the profile does not report it, this is counted by visiting every page of the profile.
+    /// See [`Client::get_scraped_user`](crate::Client::get_scraped_user).
+    pub posts: u64,
+
+    /// The ids of the posts on the first page of the profile, in the order they are shown
+    #[serde(default)]
+    pub recent_post_ids: Vec<Box<str>>,
+
+    /// The url of the user's avatar, if the profile shows one
+    #[serde(default)]
+    pub avatar: Option<Box<str>>,
+}
+
+/// A page of a user's posts, scraped from their profile.
+#[derive(Debug)]
+pub(crate) struct ScrapedUserPostsPage {
+    /// The ids of the posts on this page
+    pub ids: Vec<Box<str>>,
+
+    /// The number of the last page
+    pub last_page: u32,
+
+    /// The total number of posts, across all pages
+    pub total: Option<u64>,
+
+    /// The url of the user's avatar
+    pub avatar: Option<Box<str>>,
+}
+
+impl ScrapedUserPostsPage {
+    /// Parse this from html
+    pub(crate) fn from_html(html: &Html) -> Result<Self, FromHtmlError> {
+        let page_data: PageData = parse_page_data(html)?;
+
+        Ok(Self {
+            ids: page_data
+                .props
+                .posts
+                .data
+                .into_iter()
+                .map(|post| post.slug)
+                .collect(),
+            last_page: page_data.props.posts.last_page,
+            total: page_data.props.posts.total,
+            avatar: page_data
+                .props
+                .user
+                .and_then(|user| user.avatar)
+                .filter(|avatar| !avatar.is_empty()),
+        })
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PageData {
+    props: PageDataProps,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PageDataProps {
+    posts: PageDataPosts,
+    #[serde(default)]
+    user: Option<PageDataUser>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PageDataUser {
+    #[serde(default)]
+    avatar: Option<Box<str>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PageDataPosts {
+    data: Vec<PageDataPost>,
+    last_page: u32,
+    #[serde(default, deserialize_with = "crate::model::deserialize_count_option")]
+    total: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PageDataPost {
+    slug: Box<str>,
+}
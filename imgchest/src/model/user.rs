@@ -7,14 +7,28 @@ pub struct User {
     pub name: Box<str>,
 
     /// The number of posts
+    #[serde(deserialize_with = "crate::model::deserialize_count")]
     pub posts: u64,
 
     /// The number of comments
+    #[serde(deserialize_with = "crate::model::deserialize_count")]
     pub comments: u64,
 
     /// The time this user was created
-    #[serde(with = "time::serde::iso8601")]
+    #[serde(with = "crate::model::api_timestamp")]
     pub created: OffsetDateTime,
+
+    /// The user's recent posts, if the API included them
+    ///
+    /// The API is not documented to include these, so this is usually empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_posts: Vec<crate::Post>,
+
+    /// The url of the user's avatar, if the API included it
+    ///
+    /// The API is not documented to include this, so this is usually `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<Box<str>>,
     //#[serde(flatten)]
     //extra: std::collections::HashMap<Box<str>, serde_json::Value>,
 }
@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+/// An error that may occur while parsing a [`PostRef`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum InvalidPostRefError {
+    /// The input was not a valid id.
+    #[error("\"{0}\" is not a valid id, ids are 11 lowercase ascii alphanumeric characters")]
+    InvalidId(Box<str>),
+
+    /// The url host is not an imgchest host.
+    #[error("url host \"{0}\" is not imgchest.com, api.imgchest.com, or cdn.imgchest.com")]
+    InvalidHost(Box<str>),
+
+    /// The url path is not a known post or file path.
+    #[error("url path \"{0}\" is not a post or file path")]
+    InvalidPath(Box<str>),
+
+    /// A post was needed, but this refers to a file.
+    #[error("file urls do not identify a post")]
+    NotAPost,
+}
+
+/// A parsed reference to a post or file.
+///
+/// This can be parsed from a bare post id, a post url, or a cdn file url.
+/// The known url forms are:
+/// * `https://imgchest.com/p/{id}`, optionally followed by a secret path segment
+/// * `https://api.imgchest.com/v1/post/{id}`
+/// * `https://cdn.imgchest.com/files/{id}.{extension}`
+///
+/// Urls may use `http`, have a `www.` prefix on the main host,
+/// have a trailing slash, query, or fragment, and may omit the scheme entirely,
+/// as is common when copying links.
+/// Whitespace around the input is ignored.
+///
+/// imgchest does not document embed, short link, or vanity forms,
+/// so these forms were checked and are rejected with a descriptive error:
+/// * ids directly under the main host, like `https://imgchest.com/{id}` ([`InvalidPostRefError::InvalidPath`])
+/// * embed paths, like `https://imgchest.com/embed/{id}` ([`InvalidPostRefError::InvalidPath`])
+/// * other hosts, like `https://i.imgchest.com/{id}` ([`InvalidPostRefError::InvalidHost`])
+/// * ids that are not lowercase, like `https://imgchest.com/p/{ID}` ([`InvalidPostRefError::InvalidId`])
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PostRef {
+    /// A bare post id
+    Id(Box<str>),
+
+    /// A post url, like `https://imgchest.com/p/{id}` or `https://api.imgchest.com/v1/post/{id}`
+    Url {
+        /// The post id
+        id: Box<str>,
+
+        /// The path segment after the id, if present
+        secret: Option<Box<str>>,
+    },
+
+    /// A cdn file url, like `https://cdn.imgchest.com/files/{id}.{extension}`
+    File {
+        /// The file id
+        id: Box<str>,
+
+        /// The file extension
+        extension: Box<str>,
+    },
+}
+
+impl PostRef {
+    /// Get the id.
+    ///
+    /// This is the post id, or the file id for [`PostRef::File`].
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Id(id) => id,
+            Self::Url { id, .. } => id,
+            Self::File { id, .. } => id,
+        }
+    }
+
+    /// Get the post id.
+    ///
+    /// This fails for [`PostRef::File`], as a file's post cannot be found from its url.
+    pub fn post_id(&self) -> Result<&str, InvalidPostRefError> {
+        match self {
+            Self::Id(id) => Ok(id),
+            Self::Url { id, .. } => Ok(id),
+            Self::File { .. } => Err(InvalidPostRefError::NotAPost),
+        }
+    }
+
+    /// Get the secret path segment of a post url, if present.
+    pub fn secret(&self) -> Option<&str> {
+        match self {
+            Self::Url { secret, .. } => secret.as_deref(),
+            Self::Id(_) | Self::File { .. } => None,
+        }
+    }
+}
+
+impl FromStr for PostRef {
+    type Err = InvalidPostRefError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let url = match reqwest::Url::parse(value) {
+            Ok(url) => url,
+            Err(_error) if is_valid_id(value) => return Ok(Self::Id(value.into())),
+            Err(_error) => {
+                // This might be a url without a scheme.
+                let with_scheme = format!("https://{}", value.trim_start_matches('/'));
+                match reqwest::Url::parse(&with_scheme) {
+                    Ok(url) if value.contains('/') => url,
+                    _ => return Err(InvalidPostRefError::InvalidId(value.into())),
+                }
+            }
+        };
+
+        let host = url.host_str().unwrap_or_default();
+        let mut path_iter = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty());
+        let invalid_path = || InvalidPostRefError::InvalidPath(url.path().into());
+        match host {
+            "imgchest.com" | "www.imgchest.com" => {
+                if path_iter.next() != Some("p") {
+                    return Err(invalid_path());
+                }
+                let id = path_iter.next().ok_or_else(invalid_path)?;
+                if !is_valid_id(id) {
+                    return Err(InvalidPostRefError::InvalidId(id.into()));
+                }
+                let secret = path_iter.next().map(Box::from);
+
+                Ok(Self::Url {
+                    id: id.into(),
+                    secret,
+                })
+            }
+            "api.imgchest.com" => {
+                if path_iter.next() != Some("v1") || path_iter.next() != Some("post") {
+                    return Err(invalid_path());
+                }
+                let id = path_iter.next().ok_or_else(invalid_path)?;
+                if !is_valid_id(id) {
+                    return Err(InvalidPostRefError::InvalidId(id.into()));
+                }
+                // Other paths under a post, like `/add`, are not the post itself.
+                if path_iter.next().is_some() {
+                    return Err(invalid_path());
+                }
+
+                Ok(Self::Url {
+                    id: id.into(),
+                    secret: None,
+                })
+            }
+            "cdn.imgchest.com" => {
+                if path_iter.next() != Some("files") {
+                    return Err(invalid_path());
+                }
+                let file_name = path_iter.next().ok_or_else(invalid_path)?;
+                let (id, extension) = file_name.rsplit_once('.').ok_or_else(invalid_path)?;
+                if !is_valid_id(id) {
+                    return Err(InvalidPostRefError::InvalidId(id.into()));
+                }
+
+                Ok(Self::File {
+                    id: id.into(),
+                    extension: extension.into(),
+                })
+            }
+            host => Err(InvalidPostRefError::InvalidHost(host.into())),
+        }
+    }
+}
+
+/// Ids are composed of 11 lowercase alphanumeric chars.
+fn is_valid_id(value: &str) -> bool {
+    value.len() == 11
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_digit() || ch.is_ascii_lowercase())
+}